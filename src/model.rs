@@ -1,22 +1,64 @@
+use std::time::{Duration, Instant};
+
 use itertools::iproduct;
 use nannou::prelude::*;
 mod cluster;
+mod overlay;
+mod species;
 mod world;
+use world::chunks::TIDE_MULT;
 use world::collection::GenId;
-use world::node::{Node, NodeKind};
+use world::node::Node;
+use world::config::Config;
+use world::map::Obstacle;
+use world::mutation::MutationRates;
 use world::World;
+use strum::IntoEnumIterator;
 
-use self::cluster::Cluster;
-use self::world::node::{LifeState, SenseCalculate, SenseKind};
+use self::overlay::{Heatmap, Overlay};
+use self::species::{Metric, Species, SpeciesTracker};
+use self::world::node::{LifeState, NodeKind, SenseCalculate, SenseKind};
 use self::world::organism::Organism;
 
 pub const WINDOW_SIZE: u32 = 800;
+const INSPECTOR_WIDTH: f32 = 260.0;
+const INSPECTOR_LINE_HEIGHT: f32 = 13.0;
+/// lines longer than this are cut, ending in ~, so they stay inside the panel
+const INSPECTOR_LINE_LENGTH: usize = 42;
+const INSPECTOR_MAX_NODES: usize = 12;
+const INSPECTOR_MAX_GENES: usize = 24;
+const INSPECTOR_MAX_MUTATIONS: usize = 8;
+/// view pixels per second the arrow keys move the camera
+const CAMERA_SPEED: f32 = 400.0;
+/// how many times the zoom changes per second while +/- is held
+const CAMERA_ZOOM_SPEED: f32 = 2.0;
+/// zoom factor per line scrolled
+const WHEEL_ZOOM: f32 = 1.1;
+/// how quickly a following camera catches up, higher is stiffer
+const FOLLOW_RATE: f32 = 5.0;
+/// ticks per frame for each speed, None runs as many as fit in UNLIMITED_FRAME_TIME
+const SPEEDS: [Option<u64>; 5] = [Some(1), Some(2), Some(8), Some(64), None];
+const UNLIMITED_FRAME_TIME: Duration = Duration::from_millis(30);
+/// how far fast forwarding runs by default, and how much [ and ] change it by
+const FAST_FORWARD_TICKS: u64 = 10_000;
+/// ticks per frame while fast forwarding, the world isn't drawn until it's done
+const FAST_FORWARD_STEP: u64 = 512;
+/// how many of the latest extinct species the cluster view lists
+const EXTINCT_SHOWN: usize = 5;
 
 pub struct Camera {
     pub pos: Point2,
     pub zoom: f32,
+    /// keeps the selected organism in the middle of the view
+    pub follow: bool,
 }
 impl Camera {
+    /// zooms while keeping the world under view_pos in place
+    pub fn zoom_at(&mut self, view_pos: Point2, factor: f32) {
+        let world_pos = self.view_to_world(view_pos);
+        self.zoom *= factor;
+        self.pos = world_pos - view_pos / self.zoom;
+    }
     pub fn world_to_view(&self, pos: Point2) -> Point2 {
         (pos - self.pos) * self.zoom
     }
@@ -25,6 +67,69 @@ impl Camera {
     }
 }
 
+/// how fast the world runs
+pub struct TimeControl {
+    pub paused: bool,
+    /// index into SPEEDS
+    pub speed: usize,
+    /// run one tick on the next frame even if paused
+    pub step: bool,
+    /// the tick to run to without drawing the world
+    pub fast_forward: Option<u64>,
+    /// how many ticks the next fast forward runs
+    pub fast_forward_ticks: u64,
+    /// ticks per second actually reached, measured about once a second
+    pub tps: f32,
+    tps_since: Instant,
+    tps_tick: u64,
+}
+impl TimeControl {
+    pub fn new() -> TimeControl {
+        TimeControl {
+            paused: false,
+            speed: 0,
+            step: false,
+            fast_forward: None,
+            fast_forward_ticks: FAST_FORWARD_TICKS,
+            tps: 0.,
+            tps_since: Instant::now(),
+            tps_tick: 0,
+        }
+    }
+    fn measure_tps(&mut self, tick: u64) {
+        let elapsed = self.tps_since.elapsed().as_secs_f32();
+        if elapsed < 1. {
+            return;
+        }
+        self.tps = tick.saturating_sub(self.tps_tick) as f32 / elapsed;
+        self.tps_since = Instant::now();
+        self.tps_tick = tick;
+    }
+    /// changes how far fast forwarding runs, moving a running fast forward's target too
+    fn adjust_fast_forward(&mut self, raise: bool, tick: u64) {
+        self.fast_forward_ticks = if raise {
+            self.fast_forward_ticks + FAST_FORWARD_TICKS
+        } else {
+            self.fast_forward_ticks
+                .saturating_sub(FAST_FORWARD_TICKS)
+                .max(FAST_FORWARD_TICKS)
+        };
+        if let Some(target) = &mut self.fast_forward {
+            *target = if raise {
+                *target + FAST_FORWARD_TICKS
+            } else {
+                target.saturating_sub(FAST_FORWARD_TICKS).max(tick)
+            };
+        }
+    }
+    fn speed_name(&self) -> String {
+        match SPEEDS[self.speed] {
+            Some(ticks) => format!("{}x", ticks),
+            None => "max".to_string(),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Scene {
     World,
@@ -34,8 +139,10 @@ pub struct Model {
     pub input_state: InputState,
     pub camera: Camera,
     pub world: World,
-    pub clusters: Option<Vec<Cluster<GenId>>>,
+    pub species: SpeciesTracker,
     pub scene: Scene,
+    pub time: TimeControl,
+    pub overlay: Overlay,
 }
 #[derive(Clone)]
 pub struct NodeInfo {
@@ -51,7 +158,9 @@ pub struct InputState {
     pub minus: bool,
     pub mouse_pos: Point2,
     pub mouse_pressed: bool,
-    pub skip_toggled: bool,
+    /// the right button is held, which drags the camera
+    pub panning: bool,
+    pub tide_toggled: bool,
     pub dragged: Option<NodeInfo>,
     pub selected: Option<NodeInfo>,
 }
@@ -66,7 +175,8 @@ impl InputState {
             minus: false,
             mouse_pos: Point2::new(0.0, 0.0),
             mouse_pressed: false,
-            skip_toggled: false,
+            panning: false,
+            tide_toggled: false,
             dragged: None,
             selected: None,
         }
@@ -94,12 +204,14 @@ impl InputState {
                     Key::Left => self.left = false,
                     Key::Equals => self.plus = false,
                     Key::Minus => self.minus = false,
-                    Key::Space => self.skip_toggled = !self.skip_toggled,
+                    Key::T => self.tide_toggled = !self.tide_toggled,
                     _ => (),
                 },
                 MouseMoved(pos) => self.mouse_pos = *pos,
-                MousePressed(_) => self.mouse_pressed = true,
-                MouseReleased(_) => self.mouse_pressed = false,
+                MousePressed(MouseButton::Left) => self.mouse_pressed = true,
+                MouseReleased(MouseButton::Left) => self.mouse_pressed = false,
+                MousePressed(MouseButton::Right) => self.panning = true,
+                MouseReleased(MouseButton::Right) => self.panning = false,
                 _ => (),
             }
         }
@@ -111,11 +223,14 @@ impl Model {
             camera: Camera {
                 pos: Point2::new(100.0, 100.0),
                 zoom: 2.0,
+                follow: false,
             },
-            world: World::new(),
+            world: World::new(Config::from_args()),
             input_state: InputState::new(),
-            clusters: None,
+            species: SpeciesTracker::new(),
             scene: Scene::World,
+            time: TimeControl::new(),
+            overlay: Overlay::None,
         }
     }
     pub fn within_view(&self, pos: Point2) -> bool {
@@ -135,19 +250,12 @@ impl Model {
 
     fn draw_node_at_pos(&self, draw: &Draw, node: &Node, pos: Point2, zoom: f32) {
         let radius = node.radius * zoom;
-        let energy_mult = node.energy.max(0.) / node.max_energy() * 0.5;
-        let color = match &node.life_state {
-            LifeState::Alive { kind, .. } => match kind {
-                NodeKind::Leaf => rgb(0.5 + energy_mult, 0.7 + energy_mult, 0.5 + energy_mult),
-                NodeKind::Storage => rgb(0.7 + energy_mult, 0.5 + energy_mult, 0.5 + energy_mult),
-                NodeKind::Mouth => rgb(0.7 + energy_mult, 0.5 + energy_mult, 0.7 + energy_mult),
-                NodeKind::Egg => rgb(0.5 + energy_mult, 0.5 + energy_mult, 0.7 + energy_mult),
-                NodeKind::Spike => rgb(0.7 + energy_mult, 0.7 + energy_mult, 0.5 + energy_mult),
-                NodeKind::Shell => rgb(0.5 + energy_mult, 0.7 + energy_mult, 0.7 + energy_mult),
-                NodeKind::Jet => rgb(0.7 + energy_mult, 0.6 + energy_mult, 0.5 + energy_mult),
-            },
-            LifeState::Dead { .. } => rgb(0.5, 0.5, 0.5),
+        let energy_mult = match node.life_state {
+            LifeState::Alive { .. } => node.energy.max(0.) / node.max_energy() * 0.5,
+            LifeState::Dead { .. } => 0.,
         };
+        let (red, green, blue) = node.color();
+        let color = rgb(red + energy_mult, green + energy_mult, blue + energy_mult);
         draw.ellipse().color(color).xy(pos).radius(radius);
         if let LifeState::Alive {
             sense: Some((kind, sense)),
@@ -167,7 +275,19 @@ impl Model {
                 SenseKind::CollideRadius => rgb(0.0, 1.0, 0.2),
                 SenseKind::CollideSpeed => rgb(0.0, 1.0, 0.0),
 
+                SenseKind::CollideKin => rgb(0.5, 1.0, 0.5),
+
                 SenseKind::Eye => rgb(1.0, 0.5, 1.0),
+
+                SenseKind::Damage => rgb(1.0, 0.0, 0.5),
+                SenseKind::LostNodes => rgb(0.5, 0.0, 0.5),
+
+                SenseKind::AgeFraction => rgb(1.0, 0.3, 0.3),
+                SenseKind::Depth => rgb(0.6, 0.4, 0.2),
+                SenseKind::OrganismSize => rgb(0.8, 0.8, 0.8),
+                SenseKind::OrganismEnergy => rgb(1.0, 0.8, 0.0),
+                SenseKind::DayPhase => rgb(0.8, 0.8, 1.0),
+                SenseKind::Temperature => rgb(1.0, 0.3, 0.2),
             };
             let sense_color = match sense {
                 SenseCalculate::Calculate(sense) => {
@@ -192,29 +312,65 @@ impl Model {
     }
     pub fn view_cluster(&self, draw: &Draw) {
         draw.background().color(BLACK);
-        let Some(clusters) = &self.clusters else {return};
+        let species: Vec<&Species> = self.species.living().collect();
 
-        // split background into clusters.len() squares
-        let squares = (clusters.len() as f32).sqrt().ceil() as usize;
+        // split background into species.len() squares
+        let squares = (species.len() as f32).sqrt().ceil() as usize;
         let square_size = WINDOW_SIZE as f32 / squares as f32;
-        for (i, cluster) in clusters.iter().enumerate() {
+        for (i, species) in species.iter().enumerate() {
             let x = (i % squares) as f32 * square_size;
             let y = (i / squares) as f32 * square_size;
             let pos = vec2(x, y) + vec2(square_size, square_size) / 2.
                 - vec2(WINDOW_SIZE as f32 / 2., WINDOW_SIZE as f32 / 2.);
-            let organism = cluster
-                .points
+            let organism = species
+                .members
                 .iter()
-                .filter_map(|(tag, org_id)| self.world.organisms.get(*org_id).map(|org| (tag, org)))
-                .min_by(|(tag_1, _), (tag_2, _)| {
-                    tag_1
-                        .distance(&cluster.center)
-                        .partial_cmp(&tag_2.distance(&cluster.center))
+                .filter_map(|org_id| self.world.organisms.get(*org_id))
+                .min_by(|org_1, org_2| {
+                    org_1
+                        .genome
+                        .tag
+                        .distance(&species.center)
+                        .partial_cmp(&org_2.genome.tag.distance(&species.center))
                         .unwrap()
                 });
-            if let Some((_, organism)) = organism {
+            if let Some(organism) = organism {
                 self.draw_organism(draw, pos, organism);
             }
+            let population = species.population.last().map_or(0, |(_, count)| *count);
+            draw.text(&format!(
+                "#{} x{} since {}",
+                species.id, population, species.first_seen
+            ))
+            .color(WHITE)
+            .font_size(12)
+            .xy(pos - vec2(0., square_size / 2. - 10.))
+            .w(square_size);
+        }
+
+        // the latest species to die out, along the bottom
+        let extinct: Vec<String> = self
+            .species
+            .extinct
+            .iter()
+            .rev()
+            .take(EXTINCT_SHOWN)
+            .map(|species| {
+                format!(
+                    "#{} {}-{} ({} genes)",
+                    species.id,
+                    species.first_seen,
+                    species.extinct,
+                    species.founder.body.len()
+                )
+            })
+            .collect();
+        if !extinct.is_empty() {
+            draw.text(&format!("Extinct: {}", extinct.join(", ")))
+                .color(GRAY)
+                .font_size(12)
+                .xy(vec2(0., WINDOW_SIZE as f32 / -2. + 10.))
+                .w(WINDOW_SIZE as f32);
         }
     }
 
@@ -236,7 +392,7 @@ impl Model {
     pub fn view_world(&self, draw: &Draw, app: &App) {
         draw.background().color(BLACK);
 
-        if !self.input_state.skip_toggled {
+        if self.time.fast_forward.is_none() {
             draw.rect()
                 .color(rgb(50u8, 50, 50))
                 .xy(self.camera.world_to_view(self.world.size / 2.))
@@ -257,15 +413,16 @@ impl Model {
                 let size = size * self.camera.zoom;
                 let color = rgb(0.1 + chunk.sun * 0.15, 0.1 + chunk.sun * 0.15, 0.1);
                 draw.rect().color(color).xy(pos).wh(size);
-                // draw arrow for tide
-                // let tide: Vec2 = chunk.tide;
-                // let tide = tide * self.camera.zoom / TIDE_MULT * 20.;
-                // let color = rgb(0.5 + chunk.sun * 0.5, 0.5 + chunk.sun * 0.5, 0.5);
-                // draw.arrow()
-                //     .color(color)
-                //     .start(pos)
-                //     .end(pos + tide)
-                //     .weight(2.0 * self.camera.zoom);
+            }
+
+            let heatmap = self.overlay.heatmap(&self.world);
+            self.draw_overlay(draw, heatmap.as_ref());
+            if self.input_state.tide_toggled || self.overlay == Overlay::Tide {
+                self.draw_tide(draw);
+            }
+
+            for obstacle in self.world.map.obstacles.iter() {
+                self.draw_obstacle(draw, obstacle);
             }
 
             for node in self.world.nodes.iter() {
@@ -286,6 +443,9 @@ impl Model {
                 self.draw_muscle(&draw, pos_1, pos_2, self.camera.zoom);
             }
 
+            self.draw_legend(draw, heatmap.as_ref());
+            self.draw_inspector(draw);
+
             // mouse ray
             // let world_center = Vec2::new(1500., 1500.);
             // let mouse_pos = self.camera.view_to_world(self.input_state.mouse_pos);
@@ -330,6 +490,337 @@ impl Model {
 
         draw_gui(self, app, draw.clone());
     }
+    fn draw_overlay(&self, draw: &Draw, heatmap: Option<&Heatmap>) {
+        let (grid_size, cell_size, colors) = match heatmap {
+            Some(heatmap) => {
+                let max = heatmap.max();
+                if max <= 0. {
+                    return;
+                }
+                let colors = heatmap
+                    .values
+                    .iter()
+                    .map(|value| (*value > 0.).then(|| overlay::heat_color(value / max).into()))
+                    .collect();
+                (heatmap.grid_size, heatmap.cell_size, colors)
+            }
+            None if self.overlay == Overlay::Species => {
+                let chunks = &self.world.chunks;
+                let colors: Vec<Option<Rgba>> =
+                    overlay::dominant_species(&self.world, &self.species)
+                        .into_iter()
+                        .map(|dominant| {
+                            let (id, _) = dominant?;
+                            let color: Rgb = overlay::species_color(id).into();
+                            Some(rgba(color.red, color.green, color.blue, 0.5))
+                        })
+                        .collect();
+                let cell_size = vec2(
+                    self.world.size.x / chunks.grid_size.0 as f32,
+                    self.world.size.y / chunks.grid_size.1 as f32,
+                );
+                (chunks.grid_size, cell_size, colors)
+            }
+            None => return,
+        };
+        for (x, y) in iproduct!(0..grid_size.0, 0..grid_size.1) {
+            let Some(color) = colors[y * grid_size.0 + x] else { continue };
+            let pos = vec2(x as f32, y as f32) * cell_size + cell_size / 2.;
+            let pos = self.camera.world_to_view(pos);
+            if !self.within_view(pos) {
+                continue;
+            }
+            draw.rect()
+                .color(color)
+                .xy(pos)
+                .wh(cell_size * self.camera.zoom);
+        }
+    }
+    /// what the overlay's colors mean, in the bottom left corner
+    fn draw_legend(&self, draw: &Draw, heatmap: Option<&Heatmap>) {
+        const SWATCHES: usize = 10;
+        const SWATCH_SIZE: f32 = 16.0;
+        const MAX_SPECIES: usize = SWATCHES / 2;
+        if self.overlay == Overlay::None {
+            return;
+        }
+        let corner = vec2(WINDOW_SIZE as f32 / -2. + 10., WINDOW_SIZE as f32 / -2. + 10.);
+        let size = vec2(SWATCH_SIZE * SWATCHES as f32 + 20., 60.);
+        draw.rect()
+            .color(rgba(0., 0., 0., 0.8))
+            .xy(corner + size / 2.)
+            .wh(size);
+        let text = |text: &str, pos: Point2| {
+            draw.text(text)
+                .color(WHITE)
+                .font_size(11)
+                .no_line_wrap()
+                .left_justify()
+                .xy(pos + vec2(size.x / 2. - 10., 0.))
+                .w(size.x - 20.);
+        };
+        text(self.overlay.name(), corner + vec2(10., 48.));
+
+        let swatch = |i: usize, color: Rgba| {
+            let pos = corner + vec2(10. + (i as f32 + 0.5) * SWATCH_SIZE, 30.);
+            draw.rect().color(color).xy(pos).w_h(SWATCH_SIZE, SWATCH_SIZE);
+        };
+        match heatmap {
+            Some(heatmap) => {
+                for i in 0..SWATCHES {
+                    let fraction = (i as f32 + 0.5) / SWATCHES as f32;
+                    swatch(i, overlay::heat_color(fraction).into());
+                }
+                let max = heatmap.max();
+                let max = if max < 10. {
+                    format!("{:.2}", max)
+                } else {
+                    format!("{:.0}", max)
+                };
+                text(&format!("0 - {}", max), corner + vec2(10., 12.));
+            }
+            None => {
+                // the biggest species, which are the ones most likely to be on the map
+                let mut species: Vec<&Species> = self.species.living().collect();
+                species.sort_by_key(|species| std::cmp::Reverse(species.members.len()));
+                for (i, species) in species.iter().take(MAX_SPECIES).enumerate() {
+                    let color: Rgb = overlay::species_color(species.id).into();
+                    swatch(i * 2, rgba(color.red, color.green, color.blue, 1.));
+                    let label_pos = corner + vec2(10. + i as f32 * 2. * SWATCH_SIZE, 12.);
+                    text(&format!("#{}", species.id), label_pos);
+                }
+            }
+        }
+    }
+    fn draw_tide(&self, draw: &Draw) {
+        let chunks = &self.world.chunks;
+        let size = vec2(
+            self.world.size.x / chunks.grid_size.0 as f32,
+            self.world.size.y / chunks.grid_size.1 as f32,
+        );
+        for (x, y) in iproduct!(0..chunks.grid_size.0, 0..chunks.grid_size.1) {
+            let chunk = &chunks.grid[y * chunks.grid_size.0 + x];
+            let pos = vec2(x as f32, y as f32) * size + size / 2.;
+            let pos = self.camera.world_to_view(pos);
+            if !self.within_view(pos) {
+                continue;
+            }
+            // a full strength tide reaches most of the way across the chunk
+            let tide = chunk.tide / TIDE_MULT * size.x * 0.4 * self.camera.zoom;
+            let color = rgb(0.5 + chunk.sun * 0.5, 0.5 + chunk.sun * 0.5, 0.5);
+            draw.arrow()
+                .color(color)
+                .start(pos - tide / 2.)
+                .end(pos + tide / 2.)
+                .weight(self.camera.zoom);
+        }
+    }
+    pub fn draw_obstacle(&self, draw: &Draw, obstacle: &Obstacle) {
+        let (red, green, blue) = world::map::OBSTACLE_COLOR;
+        let color = rgb(red, green, blue);
+        match obstacle {
+            Obstacle::Circle { center, radius } => {
+                draw.ellipse()
+                    .color(color)
+                    .xy(self.camera.world_to_view(*center))
+                    .radius(radius * self.camera.zoom);
+            }
+            Obstacle::Polygon(points) => {
+                draw.polygon()
+                    .color(color)
+                    .points(points.iter().map(|point| self.camera.world_to_view(*point)));
+            }
+        }
+    }
+    /// the lines of the inspector panel for an organism, with their colors
+    fn inspector_lines(&self, organism_id: GenId, organism: &Organism) -> Vec<(String, Rgb)> {
+        let mut lines = Vec::new();
+        let nodes: Vec<&Node> = organism
+            .node_ids()
+            .iter()
+            .filter_map(|node_id| self.world.nodes.get(*node_id))
+            .filter(|node| node.is_alive())
+            .collect();
+
+        let kinds: Vec<String> = NodeKind::iter()
+            .filter_map(|kind| {
+                let count = nodes.iter().filter(|node| *node.unwrap_kind() == kind).count();
+                (count > 0).then(|| format!("{:?} {}", kind, count))
+            })
+            .collect();
+        let energy: f32 = nodes.iter().map(|node| node.energy).sum();
+        let max_energy: f32 = nodes.iter().map(|node| node.max_energy()).sum();
+        let lifespans = nodes.iter().filter_map(|node| match node.life_state {
+            LifeState::Alive { lifespan, .. } => Some(lifespan),
+            LifeState::Dead { .. } => None,
+        });
+        let (min_lifespan, max_lifespan) = lifespans.fold((u32::MAX, 0), |(min, max), lifespan| {
+            (min.min(lifespan), max.max(lifespan))
+        });
+
+        lines.push((format!("Nodes: {}", nodes.len()), rgb(1., 1., 1.)));
+        lines.push((kinds.join(", "), rgb(0.8, 0.8, 0.8)));
+        lines.push((
+            format!(
+                "Energy: {:.1}/{:.1}, {:.2} per node",
+                energy,
+                max_energy,
+                energy / nodes.len().max(1) as f32
+            ),
+            rgb(1., 1., 1.),
+        ));
+        lines.push((
+            format!(
+                "Age: {}, node lifespan {}-{}",
+                self.world.tick.saturating_sub(organism.birth_tick),
+                min_lifespan.min(max_lifespan),
+                max_lifespan
+            ),
+            rgb(1., 1., 1.),
+        ));
+        lines.push((format!("Offspring: {}", organism.offspring), rgb(1., 1., 1.)));
+        if let Some(species) = self.species.species_of(organism_id) {
+            lines.push((
+                format!("Species: #{}, since tick {}", species.id, species.first_seen),
+                rgb(1., 1., 1.),
+            ));
+        }
+
+        lines.push(("".to_string(), rgb(1., 1., 1.)));
+        // the sense and the activates go on their own lines so they're never cut off
+        for node in nodes.iter().take(INSPECTOR_MAX_NODES) {
+            let kind = node.unwrap_kind();
+            let (red, green, blue) = kind.color();
+            let color = rgb(red + 0.3, green + 0.3, blue + 0.3);
+            lines.push((format!("{:?} {:.1}", kind, node.energy), color));
+            if let (Some(sense_kind), Some(sense)) = (node.sense_kind(), node.sense()) {
+                lines.push((format!("  {:?} {:.2}", sense_kind, sense), color));
+            }
+            let activates: Vec<String> = kind
+                .outputs()
+                .iter()
+                .map(|output| format!("{:?} {:.2}", output, node.unwrap_activate(*output)))
+                .collect();
+            if !activates.is_empty() {
+                lines.push((format!("  {}", activates.join(" ")), color));
+            }
+        }
+        if nodes.len() > INSPECTOR_MAX_NODES {
+            lines.push((
+                format!("... {} more nodes", nodes.len() - INSPECTOR_MAX_NODES),
+                rgb(0.6, 0.6, 0.6),
+            ));
+        }
+
+        // each event's fields wrap onto indented lines
+        lines.push(("".to_string(), rgb(1., 1., 1.)));
+        lines.push((
+            format!("Mutations from parent: {}", organism.mutations.len()),
+            rgb(1., 1., 1.),
+        ));
+        for event in organism.mutations.iter().take(INSPECTOR_MAX_MUTATIONS) {
+            let mut line = event.name().to_string();
+            for (name, value) in event.fields() {
+                let field = format!(" {}={}", name, value);
+                if line.len() + field.len() > INSPECTOR_LINE_LENGTH {
+                    lines.push((line, rgb(0.8, 0.6, 1.)));
+                    line = " ".to_string();
+                }
+                line.push_str(&field);
+            }
+            lines.push((line, rgb(0.8, 0.6, 1.)));
+        }
+        if organism.mutations.len() > INSPECTOR_MAX_MUTATIONS {
+            lines.push((
+                format!(
+                    "... {} more mutations",
+                    organism.mutations.len() - INSPECTOR_MAX_MUTATIONS
+                ),
+                rgb(0.6, 0.6, 0.6),
+            ));
+        }
+
+        // the genes being grown are highlighted
+        lines.push(("".to_string(), rgb(1., 1., 1.)));
+        let growing = organism.growing_genes(&self.world.nodes);
+        let outline = organism.genome.body.outline();
+        for (index, gene) in outline.iter().enumerate().take(INSPECTOR_MAX_GENES) {
+            let color = if growing.contains(&index) {
+                rgb(1., 1., 0.3)
+            } else {
+                rgb(0.7, 0.7, 0.7)
+            };
+            lines.push((gene.clone(), color));
+        }
+        if outline.len() > INSPECTOR_MAX_GENES {
+            lines.push((
+                format!("... {} more genes", outline.len() - INSPECTOR_MAX_GENES),
+                rgb(0.6, 0.6, 0.6),
+            ));
+        }
+        lines
+    }
+    /// a panel next to the selected organism describing it, with its nodes outlined
+    fn draw_inspector(&self, draw: &Draw) {
+        let Some(selected) = &self.input_state.selected else { return };
+        let Some(organism_id) = selected.organism_id else { return };
+        let Some(organism) = self.world.organisms.get(organism_id) else { return };
+        let nodes: Vec<&Node> = organism
+            .node_ids()
+            .iter()
+            .filter_map(|node_id| self.world.nodes.get(*node_id))
+            .collect();
+        if nodes.is_empty() {
+            return;
+        }
+        let positions: Vec<Point2> = nodes
+            .iter()
+            .map(|node| self.camera.world_to_view(node.pos()))
+            .collect();
+        for (node, pos) in nodes.iter().zip(&positions) {
+            draw.ellipse()
+                .no_fill()
+                .stroke(WHITE)
+                .stroke_weight(1.0)
+                .xy(*pos)
+                .radius(node.radius * self.camera.zoom + 2.0);
+        }
+        let center = positions.iter().fold(vec2(0., 0.), |sum, pos| sum + *pos)
+            / positions.len() as f32;
+
+        let lines = self.inspector_lines(organism_id, organism);
+        let size = vec2(
+            INSPECTOR_WIDTH,
+            INSPECTOR_LINE_HEIGHT * lines.len() as f32 + 10.,
+        );
+        // beside the organism, but kept inside the window
+        let half_window = WINDOW_SIZE as f32 / 2.;
+        let pos = center + vec2(40. + size.x / 2., 0.);
+        let pos = vec2(
+            pos.x.clamp(size.x / 2. - half_window, half_window - size.x / 2.),
+            // a panel taller than the window hangs from the top
+            pos.y
+                .max(size.y / 2. - half_window)
+                .min(half_window - size.y / 2.),
+        );
+        draw.rect().color(rgba(0., 0., 0., 0.8)).xy(pos).wh(size);
+        let top = pos.y + size.y / 2. - 5. - INSPECTOR_LINE_HEIGHT / 2.;
+        for (i, (line, color)) in lines.iter().enumerate() {
+            let line = if line.chars().count() > INSPECTOR_LINE_LENGTH {
+                let cut: String = line.chars().take(INSPECTOR_LINE_LENGTH - 1).collect();
+                format!("{}~", cut)
+            } else {
+                line.clone()
+            };
+            draw.text(&line)
+                .color(*color)
+                .font_size(11)
+                .no_line_wrap()
+                .left_justify()
+                .x_y(pos.x, top - i as f32 * INSPECTOR_LINE_HEIGHT)
+                .w(size.x - 10.);
+        }
+    }
     pub fn draw_bone(&self, draw: &Draw, pos_1: Point2, pos_2: Point2, zoom: f32) {
         if !pos_1.is_finite() || !pos_1.is_finite() {
             return;
@@ -362,28 +853,134 @@ impl Model {
             .weight(radius);
     }
 
-    pub fn update(&mut self, _app: &App, _update: Update) {
-        if self.input_state.skip_toggled {
-            self.world.skip(512);
-        } else {
-            self.world.update();
+    pub fn update(&mut self, _app: &App, update: Update) {
+        self.run_world();
+        self.time.measure_tps(self.world.tick);
+        self.update_camera(update.since_last.as_secs_f32());
+    }
+    /// runs as many ticks as this frame gets
+    fn run_world(&mut self) {
+        if let Some(target) = self.time.fast_forward {
+            self.world
+                .skip(target.saturating_sub(self.world.tick).min(FAST_FORWARD_STEP));
+            self.species.update(&self.world);
+            if self.world.tick >= target {
+                self.time.fast_forward = None;
+            }
+            return;
         }
-        match self.scene {
-            Scene::Cluster => {
-                if self.clusters.is_none() {
-                    let mut data = Vec::new();
-                    for (org_id, org) in self.world.organisms.iter_with_ids() {
-                        data.push((org.genome.tag.clone(), org_id));
-                    }
-                    self.clusters = Some(cluster::cluster(&data));
+        if self.time.paused {
+            if self.time.step {
+                self.time.step = false;
+                self.world.update();
+                self.species.update(&self.world);
+            }
+            return;
+        }
+        match SPEEDS[self.time.speed] {
+            Some(ticks) => {
+                for _ in 0..ticks {
+                    self.world.update();
+                    self.species.update(&self.world);
+                }
+            }
+            None => {
+                let start = Instant::now();
+                while start.elapsed() < UNLIMITED_FRAME_TIME {
+                    self.world.update();
+                    self.species.update(&self.world);
                 }
             }
-            Scene::World => (),
+        }
+    }
+    /// moves the camera with the held keys and the followed organism, every frame
+    fn update_camera(&mut self, dt: f32) {
+        let input = &self.input_state;
+        let mut direction = vec2(0., 0.);
+        if input.up {
+            direction.y += 1.;
+        }
+        if input.down {
+            direction.y -= 1.;
+        }
+        if input.left {
+            direction.x -= 1.;
+        }
+        if input.right {
+            direction.x += 1.;
+        }
+        self.camera.pos += direction * CAMERA_SPEED * dt / self.camera.zoom;
+        if input.plus {
+            self.camera.zoom *= CAMERA_ZOOM_SPEED.powf(dt);
+        }
+        if input.minus {
+            self.camera.zoom /= CAMERA_ZOOM_SPEED.powf(dt);
+        }
+
+        if !self.camera.follow {
+            return;
+        }
+        let Some(organism_id) = self.input_state.selected.as_ref().and_then(|selected| selected.organism_id) else { return };
+        let Some(target) = self.organism_center(organism_id) else { return };
+        // ease towards the organism so jumps in its position don't jerk the view
+        let t = 1. - (-FOLLOW_RATE * dt).exp();
+        self.camera.pos += (target - self.camera.pos) * t;
+    }
+    /// the organism's centre of mass, None if it's gone
+    fn organism_center(&self, organism_id: GenId) -> Option<Point2> {
+        let organism = self.world.organisms.get(organism_id)?;
+        let (sum, mass) = organism
+            .node_ids()
+            .iter()
+            .filter_map(|node_id| self.world.nodes.get(*node_id))
+            .fold((vec2(0., 0.), 0.), |(sum, mass), node| {
+                let node_mass = node.radius.powi(2);
+                (sum + node.pos() * node_mass, mass + node_mass)
+            });
+        (mass > 0.).then(|| sum / mass)
+    }
+    /// selects the organism and moves the camera to it
+    fn jump_to(&mut self, organism_id: GenId) {
+        let Some(organism) = self.world.organisms.get(organism_id) else { return };
+        let Some(&node_id) = organism.node_ids().first() else { return };
+        self.input_state.selected = Some(NodeInfo {
+            node_id,
+            organism_id: Some(organism_id),
+        });
+        if let Some(center) = self.organism_center(organism_id) {
+            self.camera.pos = center;
+        }
+    }
+    fn jump_to_random(&mut self) {
+        let ids: Vec<GenId> = self.world.organisms.iter_with_ids().map(|(id, _)| id).collect();
+        if ids.is_empty() {
+            return;
+        }
+        self.jump_to(ids[random_range(0, ids.len())]);
+    }
+    fn jump_to_oldest(&mut self) {
+        let oldest = self
+            .world
+            .organisms
+            .iter_with_ids()
+            .min_by_key(|(_, organism)| organism.birth_tick)
+            .map(|(id, _)| id);
+        if let Some(oldest) = oldest {
+            self.jump_to(oldest);
+        }
+    }
+    /// writes the family tree to phylogeny.nwk and phylogeny.json in the working directory
+    fn export_phylogeny(&self) {
+        let phylogeny = &self.world.phylogeny;
+        let result = std::fs::write("phylogeny.nwk", phylogeny.to_newick())
+            .and_then(|_| std::fs::write("phylogeny.json", phylogeny.to_json()));
+        match result {
+            Ok(()) => println!("Exported {} lineages", phylogeny.len()),
+            Err(err) => println!("Couldn't export phylogeny: {}", err),
         }
     }
     pub fn event(&mut self, _app: &App, event: Event) {
-        const CAMERA_MOVE: f32 = 2.0;
-        const CAMERA_ZOOM: f32 = 1.02;
+        let last_mouse_pos = self.input_state.mouse_pos;
         self.input_state.update(&event);
 
         match event {
@@ -394,36 +991,75 @@ impl Model {
                 KeyPressed(key) => match key {
                     Key::C => match self.scene {
                         Scene::Cluster => self.scene = Scene::World,
-                        _ => {
-                            self.clusters = None;
-                            self.scene = Scene::Cluster;
-                        }
+                        _ => self.scene = Scene::Cluster,
                     },
+                    Key::K => {
+                        if self.species.metric == Metric::Genome {
+                            println!("Genome clustering always uses DBSCAN, press G for tags");
+                        } else {
+                            self.species.method = self.species.method.next();
+                            println!("Clustering with {:?}", self.species.method);
+                        }
+                    }
+                    Key::E => self.export_phylogeny(),
+                    Key::G => {
+                        self.species.metric = self.species.metric.toggle();
+                        println!("Clustering by {:?}", self.species.metric);
+                    }
+                    Key::X => self.input_state.selected = None,
+                    Key::F => {
+                        self.camera.follow = !self.camera.follow;
+                        println!("Following: {}", self.camera.follow);
+                    }
+                    Key::V => {
+                        self.overlay = self.overlay.next();
+                        println!("Overlay: {}", self.overlay.name());
+                    }
+                    Key::R => self.jump_to_random(),
+                    Key::O => self.jump_to_oldest(),
+                    Key::Space => self.time.paused = !self.time.paused,
+                    Key::Period => {
+                        self.time.paused = true;
+                        self.time.step = true;
+                    }
+                    Key::Key1 | Key::Key2 | Key::Key3 | Key::Key4 | Key::Key5 => {
+                        self.time.speed = match key {
+                            Key::Key1 => 0,
+                            Key::Key2 => 1,
+                            Key::Key3 => 2,
+                            Key::Key4 => 3,
+                            _ => 4,
+                        };
+                        self.time.paused = false;
+                    }
+                    Key::LBracket => self.time.adjust_fast_forward(false, self.world.tick),
+                    Key::RBracket => self.time.adjust_fast_forward(true, self.world.tick),
+                    Key::Return => {
+                        // pressing it again while fast forwarding stops
+                        self.time.fast_forward = if self.time.fast_forward.is_some() {
+                            None
+                        } else {
+                            Some(self.world.tick + self.time.fast_forward_ticks)
+                        };
+                    }
                     _ => (),
                 },
+                MouseMoved(pos) if self.input_state.panning => {
+                    self.camera.pos -= (pos - last_mouse_pos) / self.camera.zoom;
+                }
+                MouseWheel(delta, _) => {
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        // roughly one line per 20 pixels
+                        MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 20.,
+                    };
+                    let mouse_pos = self.input_state.mouse_pos;
+                    self.camera.zoom_at(mouse_pos, WHEEL_ZOOM.powf(lines));
+                }
                 _ => (),
             },
             _ => (),
         }
-        if self.input_state.up {
-            self.camera.pos.y += CAMERA_MOVE / self.camera.zoom;
-        }
-        if self.input_state.down {
-            self.camera.pos.y -= CAMERA_MOVE / self.camera.zoom;
-        }
-        if self.input_state.left {
-            self.camera.pos.x -= CAMERA_MOVE / self.camera.zoom;
-        }
-        if self.input_state.right {
-            self.camera.pos.x += CAMERA_MOVE / self.camera.zoom;
-        }
-        if self.input_state.plus {
-            self.camera.zoom *= CAMERA_ZOOM;
-        }
-        if self.input_state.minus {
-            self.camera.zoom /= CAMERA_ZOOM;
-        }
-
         if self.input_state.mouse_pressed {
             // move node towards mouse pos
             let mouse_pos = self.camera.view_to_world(self.input_state.mouse_pos);
@@ -432,15 +1068,13 @@ impl Model {
                 let mut nearest_dist = 100000000.0;
                 let mut nearest_node = None;
                 let mut nearest_org = None;
-                for (org_id, org) in self.world.organisms.iter_with_ids() {
-                    for node_id in org.node_ids().iter() {
-                        let Some(node) = &self.world.nodes.get(*node_id) else {continue};
-                        let dist = node.pos().distance(mouse_pos);
-                        if dist < nearest_dist {
-                            nearest_dist = dist;
-                            nearest_node = Some(*node_id);
-                            nearest_org = Some(org_id);
-                        }
+                for (node_id, node) in self.world.nodes.iter_with_ids() {
+                    let Some(org_id) = node.owner() else {continue};
+                    let dist = node.pos().distance(mouse_pos);
+                    if dist < nearest_dist {
+                        nearest_dist = dist;
+                        nearest_node = Some(node_id);
+                        nearest_org = Some(org_id);
                     }
                 }
                 if let Some(node_id) = nearest_node {
@@ -466,12 +1100,8 @@ impl Model {
             }
         } else {
             if let Some(dragged) = &self.input_state.dragged {
-                let Some(node) = &mut self.world.nodes.get(dragged.node_id) else {return};
-                println!("Node: {:#?}", node);
-                if let Some(organism_id) = dragged.organism_id {
-                    let Some(organism) = &mut self.world.organisms.get(organism_id) else {return};
-                    println!("{}", organism.genome);
-                    println!("{}", organism.brain);
+                if self.world.nodes.get(dragged.node_id).is_none() {
+                    return;
                 }
                 self.input_state.selected = self.input_state.dragged.take();
             }
@@ -490,12 +1120,40 @@ fn draw_gui(model: &Model, app: &App, draw: Draw) {
             .left_justify();
         y -= 20.0;
     };
-    let texts = vec![
+    let mut texts = vec![
         format!("FPS: {}", app.fps() as u32),
+        format!("Tick: {}", model.world.tick),
+        format!("TPS: {}", model.time.tps as u32),
+        match (model.time.fast_forward, model.time.paused) {
+            (Some(target), _) => format!("To tick {}", target),
+            (None, true) => "Paused".to_string(),
+            (None, false) => format!("Speed: {}", model.time.speed_name()),
+        },
+        format!("Skip: {} ticks", model.time.fast_forward_ticks),
         format!("Nodes: {}", model.world.nodes.len()),
         format!("Bones: {}", model.world.bones.len()),
         format!("Muscles: {}", model.world.muscles.len()),
+        format!("Species: {}", model.species.living().count()),
+        format!("Extinct: {}", model.species.extinct.len()),
+        format!("By: {}", model.species.method_name()),
+        format!("Lineages: {}", model.world.phylogeny.len()),
     ];
+    let rates = MutationRates::mean(
+        model
+            .world
+            .organisms
+            .iter()
+            .map(|organism| &organism.genome.rates),
+    );
+    if let Some(rates) = rates {
+        texts.extend([
+            format!("Mutate: {:.2}", rates.chance),
+            format!("Body count: {:.1}", rates.body_count),
+            format!("Both: {:.2}", rates.both_chance),
+            format!("Body: {:.2}", rates.body_chance),
+            format!("Step: {:.3}", rates.gradual_step),
+        ]);
+    }
 
     // draw rect behind
     draw.rect()
@@ -1,99 +1,381 @@
+use super::world::genome::Genome;
 use super::world::tag::Tag;
 
+use rand::seq::SliceRandom;
 use rand::Rng;
 
+/// how many points the silhouette is measured on, it's quadratic in this
+const SILHOUETTE_SAMPLE: usize = 400;
+/// how many points k is picked on
+const AUTO_K_SAMPLE: usize = 1000;
+/// how many members are compared to find a cluster's medoid
+const MEDOID_SAMPLE: usize = 32;
+
+/// something that can be clustered
+pub trait Point: Clone {
+    /// whether distance keeps the triangle inequality, which lets radius queries skip points
+    const METRIC: bool = true;
+    fn distance(&self, other: &Self) -> f32;
+}
+
+/// points that can be averaged, which k-means needs
+pub trait Mean: Point {
+    fn mean(points: &[&Self]) -> Self;
+}
+
+impl Point for Tag {
+    fn distance(&self, other: &Tag) -> f32 {
+        Tag::distance(self, other)
+    }
+}
+
+impl Point for Genome {
+    // normalized edit distance and greedy connect matching can break the triangle inequality
+    const METRIC: bool = false;
+    fn distance(&self, other: &Genome) -> f32 {
+        Genome::distance(self, other)
+    }
+}
+
+impl Mean for Tag {
+    fn mean(points: &[&Tag]) -> Tag {
+        let sum = points
+            .iter()
+            .fold(Tag::zero(), |sum, point| sum + (*point).clone());
+        sum / points.len() as f32
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct Cluster<T> {
-    pub center: Tag,
-    pub points: Vec<(Tag, T)>,
+pub struct Cluster<P, T> {
+    pub center: P,
+    pub points: Vec<(P, T)>,
 }
 
-pub fn cluster<T>(data: &[(Tag, T)]) -> Vec<Cluster<T>>
-where
-    T: Clone,
-{
-    k_means(data, 16, 100)
+#[derive(Debug, Clone, Copy)]
+pub enum Method {
+    KMeans { k: usize },
+    /// k-means with the k from 2 to max_k that has the best silhouette
+    AutoKMeans { max_k: usize },
+    /// points with at least min_points neighbors within radius are joined into clusters,
+    /// points far from all of them are left out
+    Dbscan { radius: f32, min_points: usize },
 }
 
-fn k_means<T>(data: &[(Tag, T)], k: usize, max_iterations: usize) -> Vec<Cluster<T>>
+impl Default for Method {
+    fn default() -> Self {
+        Method::AutoKMeans { max_k: 24 }
+    }
+}
+
+impl Method {
+    /// cycles through the methods with their default parameters
+    pub fn next(self) -> Method {
+        match self {
+            Method::AutoKMeans { .. } => Method::KMeans { k: 16 },
+            Method::KMeans { .. } => Method::Dbscan {
+                radius: 0.5,
+                min_points: 4,
+            },
+            Method::Dbscan { .. } => Method::default(),
+        }
+    }
+}
+
+pub fn cluster<P, T>(data: &[(P, T)], method: Method) -> Vec<Cluster<P, T>>
 where
+    P: Mean,
     T: Clone,
 {
-    let mut rng = rand::thread_rng();
-
-    // Step 1: Initialize clusters using k-means++
-    let mut clusters: Vec<Cluster<T>> = Vec::new();
-    clusters.push(Cluster {
-        center: data[rng.gen_range(0..data.len())].0.clone(),
-        points: Vec::new(),
-    });
+    match method {
+        Method::KMeans { k } => k_means(data, k, 100),
+        Method::AutoKMeans { max_k } => auto_k_means(data, max_k),
+        Method::Dbscan { radius, min_points } => dbscan(data, radius, min_points),
+    }
+}
 
-    while clusters.len() < k {
-        // For each data point, calculate the distance to the nearest existing cluster
-        let distances: Vec<f32> = data
-            .iter()
-            .map(|(point, _)| {
-                clusters
-                    .iter()
-                    .map(|cluster| point.distance(&cluster.center))
-                    .min_by(|a, b| a.partial_cmp(b).unwrap())
-                    .unwrap()
-            })
-            .collect();
+fn nearest_center<P: Point>(point: &P, centers: &[P]) -> (usize, f32) {
+    centers
+        .iter()
+        .map(|center| point.distance(center))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .unwrap()
+}
 
-        // Choose the next cluster center with probability proportional to the square of the distance
-        let sum_distances: f32 = distances.iter().sum();
-        let probability: Vec<f32> = distances
-            .iter()
-            .map(|d| d.powi(2) / sum_distances)
-            .collect();
+/// picks k starting centers, each with probability proportional to its squared distance
+/// from the centers picked so far
+fn k_means_plus_plus<P: Point, T>(data: &[(P, T)], k: usize) -> Vec<P> {
+    let mut rng = rand::thread_rng();
+    let mut centers = vec![data[rng.gen_range(0..data.len())].0.clone()];
+    let mut distances: Vec<f32> = data
+        .iter()
+        .map(|(point, _)| point.distance(&centers[0]).powi(2))
+        .collect();
 
-        let chosen_index = (0..data.len())
-            .max_by(|&i, &j| probability[i].partial_cmp(&probability[j]).unwrap())
-            .unwrap();
+    while centers.len() < k {
+        let sum: f32 = distances.iter().sum();
+        let chosen_index = if sum > 0. {
+            let mut target = rng.gen_range(0.0..sum);
+            distances
+                .iter()
+                .position(|distance| {
+                    target -= distance;
+                    target < 0.
+                })
+                .unwrap_or(data.len() - 1)
+        } else {
+            // every point is on a center already
+            rng.gen_range(0..data.len())
+        };
+        let center = data[chosen_index].0.clone();
+        for ((point, _), distance) in data.iter().zip(distances.iter_mut()) {
+            *distance = distance.min(point.distance(&center).powi(2));
+        }
+        centers.push(center);
+    }
+    centers
+}
 
-        clusters.push(Cluster {
-            center: data[chosen_index].0.clone(),
-            points: Vec::new(),
-        });
+pub fn k_means<P, T>(data: &[(P, T)], k: usize, max_iterations: usize) -> Vec<Cluster<P, T>>
+where
+    P: Mean,
+    T: Clone,
+{
+    let k = k.min(data.len());
+    if k == 0 {
+        return Vec::new();
     }
+    let mut centers = k_means_plus_plus(data, k);
+    let mut assignments = vec![usize::MAX; data.len()];
 
-    // Step 2: Assign data points to clusters and update cluster centers
     for _iteration in 0..max_iterations {
-        for cluster in &mut clusters {
-            cluster.points.clear();
+        let mut changed = false;
+        for ((point, _), assignment) in data.iter().zip(assignments.iter_mut()) {
+            let (nearest, _) = nearest_center(point, &centers);
+            if *assignment != nearest {
+                *assignment = nearest;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
         }
-        // Assign each data point to the nearest cluster
-        for (point, owner) in data {
-            let nearest_cluster_index = clusters
+        for (i, center) in centers.iter_mut().enumerate() {
+            let members: Vec<&P> = data
+                .iter()
+                .zip(&assignments)
+                .filter(|(_, assignment)| **assignment == i)
+                .map(|((point, _), _)| point)
+                .collect();
+            if !members.is_empty() {
+                *center = P::mean(&members);
+            }
+        }
+    }
+
+    let mut clusters: Vec<Cluster<P, T>> = centers
+        .into_iter()
+        .map(|center| Cluster {
+            center,
+            points: Vec::new(),
+        })
+        .collect();
+    for (point, assignment) in data.iter().zip(assignments) {
+        clusters[assignment].points.push(point.clone());
+    }
+    clusters
+}
+
+/// how well the points fit their clusters compared to the next best cluster, from -1 to 1,
+/// measured on a sample of the points
+fn silhouette<P: Point, T>(clusters: &[Cluster<P, T>]) -> f32 {
+    let mut rng = rand::thread_rng();
+    let mut sample: Vec<(usize, &P)> = clusters
+        .iter()
+        .enumerate()
+        .flat_map(|(i, cluster)| cluster.points.iter().map(move |(point, _)| (i, point)))
+        .collect();
+    sample.shuffle(&mut rng);
+    sample.truncate(SILHOUETTE_SAMPLE);
+
+    let scores: Vec<f32> = sample
+        .iter()
+        .filter_map(|(i, point)| {
+            let mut sums = vec![(0., 0); clusters.len()];
+            for (j, other) in sample.iter() {
+                if std::ptr::eq(*point, *other) {
+                    continue;
+                }
+                sums[*j].0 += point.distance(other);
+                sums[*j].1 += 1;
+            }
+            let mean = |(sum, count): (f32, usize)| (count > 0).then(|| sum / count as f32);
+            // a point alone in its cluster has no silhouette
+            let inside = mean(sums[*i])?;
+            let outside = sums
                 .iter()
                 .enumerate()
-                .min_by(|(_, a), (_, b)| {
-                    a.center
-                        .distance(point)
-                        .partial_cmp(&b.center.distance(point))
-                        .unwrap()
-                })
-                .map(|(index, _)| index)
-                .unwrap();
+                .filter(|(j, _)| j != i)
+                .filter_map(|(_, sum)| mean(*sum))
+                .min_by(|a, b| a.partial_cmp(b).unwrap())?;
+            let spread = inside.max(outside);
+            Some(if spread > 0. {
+                (outside - inside) / spread
+            } else {
+                0.
+            })
+        })
+        .collect();
+    if scores.is_empty() {
+        return -1.;
+    }
+    scores.iter().sum::<f32>() / scores.len() as f32
+}
 
-            clusters[nearest_cluster_index]
-                .points
-                .push((point.clone(), owner.clone()));
+/// runs k-means on a sample for every k up to max_k, and uses the k with the best silhouette
+pub fn auto_k_means<P, T>(data: &[(P, T)], max_k: usize) -> Vec<Cluster<P, T>>
+where
+    P: Mean,
+    T: Clone,
+{
+    if data.len() < 3 {
+        return k_means(data, 1, 100);
+    }
+    let sample: Vec<(P, ())> = data
+        .choose_multiple(&mut rand::thread_rng(), AUTO_K_SAMPLE)
+        .map(|(point, _)| (point.clone(), ()))
+        .collect();
+    let k = (2..=max_k.min(sample.len() - 1))
+        .map(|k| (silhouette(&k_means(&sample, k, 30)), k))
+        .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+        .map_or(1, |(_, k)| k);
+    k_means(data, k, 100)
+}
+
+/// the member closest to all the others, from a sample of the members
+fn medoid<P: Point, T>(points: &[(P, T)]) -> P {
+    let sample: Vec<&P> = points
+        .choose_multiple(&mut rand::thread_rng(), MEDOID_SAMPLE)
+        .map(|(point, _)| point)
+        .collect();
+    sample
+        .iter()
+        .map(|point| {
+            let total: f32 = sample.iter().map(|other| point.distance(other)).sum();
+            (total, point)
+        })
+        .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+        .map(|(_, point)| (*point).clone())
+        .unwrap()
+}
+
+/// the points sorted by distance to one of them, so that by the triangle inequality only a
+/// window of the sorted points can be within a radius of any point.
+/// points that aren't metric aren't sorted and every point is checked
+struct PivotIndex {
+    /// distance from each point to the pivot
+    keys: Vec<f32>,
+    /// point indices sorted by key
+    order: Vec<usize>,
+}
+
+impl PivotIndex {
+    fn new<P: Point, T>(data: &[(P, T)]) -> PivotIndex {
+        if !P::METRIC {
+            return PivotIndex {
+                keys: Vec::new(),
+                order: (0..data.len()).collect(),
+            };
         }
+        let keys: Vec<f32> = data
+            .iter()
+            .map(|(point, _)| point.distance(&data[0].0))
+            .collect();
+        let mut order: Vec<usize> = (0..data.len()).collect();
+        order.sort_by(|a, b| keys[*a].partial_cmp(&keys[*b]).unwrap());
+        PivotIndex { keys, order }
+    }
+    /// indices of the points within radius of point i, including i
+    fn neighbors<P: Point, T>(&self, data: &[(P, T)], i: usize, radius: f32) -> Vec<usize> {
+        let window = if P::METRIC {
+            let key = self.keys[i];
+            let start = self
+                .order
+                .partition_point(|j| self.keys[*j] < key - radius);
+            let end = self
+                .order
+                .partition_point(|j| self.keys[*j] <= key + radius);
+            &self.order[start..end]
+        } else {
+            &self.order[..]
+        };
+        window
+            .iter()
+            .copied()
+            .filter(|j| data[i].0.distance(&data[*j].0) <= radius)
+            .collect()
+    }
+}
 
-        // Update cluster centers
-        for cluster in &mut clusters {
-            if !cluster.points.is_empty() {
-                let sum: Tag = cluster
-                    .points
-                    .iter()
-                    .fold(Tag::zero(), |sum, (point, _)| sum + point.clone());
-                let new_center = sum / cluster.points.len() as f32;
-                cluster.center = new_center;
+/// density based clustering, which finds any number of clusters of any shape, centers are medoids
+pub fn dbscan<P, T>(data: &[(P, T)], radius: f32, min_points: usize) -> Vec<Cluster<P, T>>
+where
+    P: Point,
+    T: Clone,
+{
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let index = PivotIndex::new(data);
+    let mut labels: Vec<Option<usize>> = vec![None; data.len()];
+    // visited points have been queued once, and are never queued again
+    let mut visited = vec![false; data.len()];
+    let mut cluster_count = 0;
+    for i in 0..data.len() {
+        if visited[i] {
+            continue;
+        }
+        visited[i] = true;
+        let reached = index.neighbors(data, i, radius);
+        if reached.len() < min_points {
+            // noise, unless a core point reaches it later
+            continue;
+        }
+        labels[i] = Some(cluster_count);
+        let mut frontier = Vec::new();
+        let mut join = |reached: Vec<usize>, frontier: &mut Vec<usize>| {
+            for j in reached {
+                if labels[j].is_none() {
+                    labels[j] = Some(cluster_count);
+                }
+                if !visited[j] {
+                    visited[j] = true;
+                    frontier.push(j);
+                }
+            }
+        };
+        join(reached, &mut frontier);
+        while let Some(j) = frontier.pop() {
+            let reached = index.neighbors(data, j, radius);
+            if reached.len() >= min_points {
+                join(reached, &mut frontier);
             }
         }
+        cluster_count += 1;
     }
 
-    clusters
+    let mut members: Vec<Vec<(P, T)>> = vec![Vec::new(); cluster_count];
+    for (point, label) in data.iter().zip(labels) {
+        if let Some(label) = label {
+            members[label].push(point.clone());
+        }
+    }
+    members
+        .into_iter()
+        .map(|points| Cluster {
+            center: medoid(&points),
+            points,
+        })
+        .collect()
 }
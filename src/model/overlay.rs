@@ -0,0 +1,164 @@
+use nannou::prelude::*;
+
+use super::species::SpeciesTracker;
+use super::world::chunks::TIDE_MULT;
+use super::world::node::LifeState;
+use super::world::World;
+
+/// what's painted over the world to show where niches are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    None,
+    Sun,
+    Tide,
+    /// energy of living nodes
+    Energy,
+    /// energy left in dead nodes
+    Dead,
+    /// how many nodes are in each collider cell
+    Density,
+    /// the species with the most living nodes in each chunk
+    Species,
+}
+
+impl Overlay {
+    pub fn next(self) -> Overlay {
+        match self {
+            Overlay::None => Overlay::Sun,
+            Overlay::Sun => Overlay::Tide,
+            Overlay::Tide => Overlay::Energy,
+            Overlay::Energy => Overlay::Dead,
+            Overlay::Dead => Overlay::Density,
+            Overlay::Density => Overlay::Species,
+            Overlay::Species => Overlay::None,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Overlay::None => "None",
+            Overlay::Sun => "Sun",
+            Overlay::Tide => "Tide speed",
+            Overlay::Energy => "Living energy",
+            Overlay::Dead => "Dead matter",
+            Overlay::Density => "Nodes per cell",
+            Overlay::Species => "Dominant species",
+        }
+    }
+    /// the values to paint on a grid, None for the overlays that aren't a scale
+    pub fn heatmap(self, world: &World) -> Option<Heatmap> {
+        let chunks = &world.chunks;
+        match self {
+            Overlay::Sun => Some(Heatmap::new(
+                chunks.grid.iter().map(|chunk| chunk.sun).collect(),
+                chunks.grid_size,
+                world.size,
+            )),
+            Overlay::Tide => Some(Heatmap::new(
+                chunks
+                    .grid
+                    .iter()
+                    .map(|chunk| chunk.tide.length() / TIDE_MULT)
+                    .collect(),
+                chunks.grid_size,
+                world.size,
+            )),
+            Overlay::Energy => Some(energy_heatmap(world, true)),
+            Overlay::Dead => Some(energy_heatmap(world, false)),
+            Overlay::Density => Some(Heatmap::new(
+                world
+                    .collider
+                    .grid
+                    .iter()
+                    .map(|cell| cell.len() as f32)
+                    .collect(),
+                world.collider.grid_size,
+                world.size,
+            )),
+            Overlay::None | Overlay::Species => None,
+        }
+    }
+}
+
+/// a value for each cell of a grid covering the world, indexed by y * width + x
+pub struct Heatmap {
+    pub values: Vec<f32>,
+    pub grid_size: (usize, usize),
+    pub cell_size: Vec2,
+}
+
+impl Heatmap {
+    fn new(values: Vec<f32>, grid_size: (usize, usize), world_size: Vec2) -> Heatmap {
+        Heatmap {
+            values,
+            grid_size,
+            cell_size: vec2(
+                world_size.x / grid_size.0 as f32,
+                world_size.y / grid_size.1 as f32,
+            ),
+        }
+    }
+    pub fn max(&self) -> f32 {
+        self.values.iter().copied().fold(0., f32::max)
+    }
+    /// the cell's index, clamped to the grid
+    fn index(&self, pos: Point2) -> usize {
+        let x = ((pos.x / self.cell_size.x).max(0.) as usize).min(self.grid_size.0 - 1);
+        let y = ((pos.y / self.cell_size.y).max(0.) as usize).min(self.grid_size.1 - 1);
+        y * self.grid_size.0 + x
+    }
+}
+
+/// summed energy of the living or the dead nodes in each chunk
+fn energy_heatmap(world: &World, alive: bool) -> Heatmap {
+    let grid_size = world.chunks.grid_size;
+    let mut heatmap = Heatmap::new(vec![0.; grid_size.0 * grid_size.1], grid_size, world.size);
+    for node in world.nodes.iter() {
+        if node.is_alive() != alive {
+            continue;
+        }
+        let index = heatmap.index(node.pos());
+        heatmap.values[index] += node.energy.max(0.);
+    }
+    heatmap
+}
+
+/// from blue for nothing through green and yellow to red for the most
+pub fn heat_color(fraction: f32) -> Hsva {
+    let fraction = fraction.clamp(0., 1.);
+    hsva(0.66 * (1. - fraction), 0.9, 0.9, 0.15 + fraction * 0.45)
+}
+
+/// a distinct color for each species id
+pub fn species_color(id: usize) -> Hsv {
+    // golden ratio steps spread neighbouring ids around the hue circle
+    hsv((id as f32 * 0.618_034).fract(), 0.7, 0.9)
+}
+
+/// the id of the species with the most living nodes in each chunk, and how many it has there
+pub fn dominant_species(world: &World, species: &SpeciesTracker) -> Vec<Option<(usize, usize)>> {
+    let grid_size = world.chunks.grid_size;
+    let cell_count = grid_size.0 * grid_size.1;
+    let grid = Heatmap::new(Vec::new(), grid_size, world.size);
+    let mut dominant: Vec<Option<(usize, usize)>> = vec![None; cell_count];
+    let mut counts = vec![0; cell_count];
+    for species in species.living() {
+        counts.iter_mut().for_each(|count| *count = 0);
+        let nodes = species
+            .members
+            .iter()
+            .filter_map(|org_id| world.organisms.get(*org_id))
+            .flat_map(|organism| organism.node_ids().iter())
+            .filter_map(|node_id| world.nodes.get(*node_id));
+        for node in nodes {
+            if let LifeState::Alive { .. } = node.life_state {
+                counts[grid.index(node.pos())] += 1;
+            }
+        }
+        for (count, dominant) in counts.iter().zip(dominant.iter_mut()) {
+            if *count > 0 && dominant.is_none_or(|(_, most)| *count > most) {
+                *dominant = Some((species.id, *count));
+            }
+        }
+    }
+    dominant
+}
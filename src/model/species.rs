@@ -0,0 +1,285 @@
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use super::cluster::{self, Mean, Method};
+use super::world::collection::GenId;
+use super::world::genome::Genome;
+use super::world::tag::Tag;
+use super::world::World;
+
+/// how many ticks between reclustering the population
+pub const SPECIES_INTERVAL: u64 = 1024;
+/// how far a cluster's center tag can move between updates and still be the same species
+const MATCH_DISTANCE: f32 = 1.0;
+/// how far a cluster's representative genome can change and still be the same species
+const GENOME_MATCH_DISTANCE: f32 = 0.6;
+/// genome distance is slow, so only about this many organisms are clustered and the rest join
+/// the nearest cluster
+const GENOME_SAMPLE: usize = 300;
+const GENOME_RADIUS: f32 = 0.3;
+const GENOME_MIN_POINTS: usize = 3;
+/// a longer population history is thinned to half as many entries
+const MAX_POPULATION_HISTORY: usize = 256;
+
+/// what organisms are compared by when clustering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Tag,
+    Genome,
+}
+
+impl Metric {
+    pub fn toggle(self) -> Metric {
+        match self {
+            Metric::Tag => Metric::Genome,
+            Metric::Genome => Metric::Tag,
+        }
+    }
+}
+
+/// a cluster of organisms that keeps its identity as it drifts over time
+#[derive(Debug, Clone)]
+pub struct Species {
+    pub id: usize,
+    /// mean tag of the members
+    pub center: Tag,
+    /// genome of the member closest to the center
+    pub representative: Genome,
+    pub first_seen: u64,
+    /// the tick and number of members at each update, thinned out as it gets long
+    pub population: Vec<(u64, usize)>,
+    /// the genome of the member closest to the center when it was first seen
+    pub founder: Genome,
+    pub members: Vec<GenId>,
+}
+
+impl Species {
+    fn record_population(&mut self, tick: u64, count: usize) {
+        self.population.push((tick, count));
+        if self.population.len() > MAX_POPULATION_HISTORY {
+            // keep every other entry, and always the latest
+            let latest = *self.population.last().unwrap();
+            let mut i = 0;
+            self.population.retain(|_| {
+                i += 1;
+                i % 2 == 1
+            });
+            if self.population.last() != Some(&latest) {
+                self.population.push(latest);
+            }
+        }
+    }
+}
+
+/// what's kept of a species once it dies out
+#[derive(Debug, Clone)]
+pub struct ExtinctSpecies {
+    pub id: usize,
+    pub first_seen: u64,
+    /// the tick of the first update it was missing from
+    pub extinct: u64,
+    pub founder: Genome,
+}
+
+/// a cluster found this update, before it's matched to a species
+struct Group {
+    center: Tag,
+    representative: Genome,
+    members: Vec<GenId>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpeciesTracker {
+    /// the living species
+    pub species: Vec<Species>,
+    pub extinct: Vec<ExtinctSpecies>,
+    pub method: Method,
+    pub metric: Metric,
+    next_id: usize,
+    last_update: Option<u64>,
+}
+
+impl SpeciesTracker {
+    pub fn new() -> SpeciesTracker {
+        SpeciesTracker {
+            species: Vec::new(),
+            extinct: Vec::new(),
+            method: Method::default(),
+            metric: Metric::Tag,
+            next_id: 0,
+            last_update: None,
+        }
+    }
+    /// what the population is clustered by and how, the method only applies to tags
+    pub fn method_name(&self) -> &'static str {
+        match (self.metric, self.method) {
+            (Metric::Genome, _) => "genome DBSCAN",
+            (Metric::Tag, Method::KMeans { .. }) => "tag k-means",
+            (Metric::Tag, Method::AutoKMeans { .. }) => "tag auto k",
+            (Metric::Tag, Method::Dbscan { .. }) => "tag DBSCAN",
+        }
+    }
+    pub fn living(&self) -> impl Iterator<Item = &Species> {
+        self.species.iter()
+    }
+    pub fn species_of(&self, organism_id: GenId) -> Option<&Species> {
+        self.living()
+            .find(|species| species.members.contains(&organism_id))
+    }
+    /// clusters by tag with the chosen method
+    fn group_by_tag(&self, world: &World) -> Vec<Group> {
+        let data: Vec<(Tag, GenId)> = world
+            .organisms
+            .iter_with_ids()
+            .map(|(org_id, org)| (org.genome.tag.clone(), org_id))
+            .collect();
+        cluster::cluster(&data, self.method)
+            .into_iter()
+            .filter_map(|cluster| {
+                let (_, closest) = cluster.points.iter().min_by(|(a, _), (b, _)| {
+                    a.distance(&cluster.center)
+                        .partial_cmp(&b.distance(&cluster.center))
+                        .unwrap()
+                })?;
+                Some(Group {
+                    representative: world.organisms.get(*closest)?.genome.clone(),
+                    center: cluster.center,
+                    members: cluster.points.iter().map(|(_, org_id)| *org_id).collect(),
+                })
+            })
+            .collect()
+    }
+    /// clusters a sample by genome with DBSCAN, then everyone joins the closest medoid in reach.
+    /// genomes can't be averaged, so the chosen method isn't used
+    fn group_by_genome(&self, world: &World) -> Vec<Group> {
+        let sample_chance = GENOME_SAMPLE as f32 / world.organisms.len().max(1) as f32;
+        let sample: Vec<(Genome, GenId)> = world
+            .organisms
+            .iter_with_ids()
+            .filter(|_| rand::random::<f32>() < sample_chance)
+            .map(|(org_id, org)| (org.genome.clone(), org_id))
+            .collect();
+        let medoids: Vec<Genome> = cluster::dbscan(&sample, GENOME_RADIUS, GENOME_MIN_POINTS)
+            .into_iter()
+            .map(|cluster| cluster.center)
+            .collect();
+
+        let organisms: Vec<_> = world.organisms.iter_with_ids().collect();
+        let nearest: Vec<Option<usize>> = organisms
+            .par_iter()
+            .map(|(_, org)| {
+                medoids
+                    .iter()
+                    .map(|medoid| org.genome.distance(medoid))
+                    .enumerate()
+                    .filter(|(_, distance)| *distance <= GENOME_RADIUS * 2.)
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                    .map(|(i, _)| i)
+            })
+            .collect();
+        let mut groups: Vec<(Vec<&Tag>, Vec<GenId>)> = vec![(Vec::new(), Vec::new()); medoids.len()];
+        for ((org_id, org), nearest) in organisms.iter().zip(nearest) {
+            let Some(i) = nearest else { continue };
+            groups[i].0.push(&org.genome.tag);
+            groups[i].1.push(*org_id);
+        }
+
+        medoids
+            .into_iter()
+            .zip(groups)
+            .filter(|(_, (tags, _))| !tags.is_empty())
+            .map(|(representative, (tags, members))| Group {
+                center: Tag::mean(&tags),
+                representative,
+                members,
+            })
+            .collect()
+    }
+    /// reclusters the population if it's been long enough since the last time
+    pub fn update(&mut self, world: &World) {
+        if let Some(last_update) = self.last_update {
+            if world.tick < last_update + SPECIES_INTERVAL {
+                return;
+            }
+        }
+        self.last_update = Some(world.tick);
+
+        let groups: Vec<Group> = match self.metric {
+            Metric::Tag => self.group_by_tag(world),
+            Metric::Genome => self.group_by_genome(world),
+        }
+        .into_iter()
+        .filter(|group| !group.members.is_empty())
+        .collect();
+
+        // match the closest pairs of groups and living species first
+        let mut pairs = Vec::new();
+        for (group_index, group) in groups.iter().enumerate() {
+            for (species_index, species) in self.species.iter().enumerate() {
+                // as a fraction of how far it can be to match
+                let distance = match self.metric {
+                    Metric::Tag => group.center.distance(&species.center) / MATCH_DISTANCE,
+                    Metric::Genome => {
+                        group.representative.distance(&species.representative)
+                            / GENOME_MATCH_DISTANCE
+                    }
+                };
+                if distance < 1. {
+                    pairs.push((distance, group_index, species_index));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut group_species = vec![None; groups.len()];
+        let mut species_matched = vec![false; self.species.len()];
+        for (_, group_index, species_index) in pairs {
+            if group_species[group_index].is_some() || species_matched[species_index] {
+                continue;
+            }
+            group_species[group_index] = Some(species_index);
+            species_matched[species_index] = true;
+        }
+
+        let mut new_groups = Vec::new();
+        for (group, species_index) in groups.into_iter().zip(group_species) {
+            match species_index {
+                Some(species_index) => {
+                    let species = &mut self.species[species_index];
+                    species.center = group.center;
+                    species.representative = group.representative;
+                    species.record_population(world.tick, group.members.len());
+                    species.members = group.members;
+                }
+                None => new_groups.push(group),
+            }
+        }
+
+        // unmatched species died out, only a small record of them is kept
+        let mut matched = species_matched.into_iter();
+        let extinct = &mut self.extinct;
+        self.species.retain(|species| {
+            if matched.next().unwrap() {
+                return true;
+            }
+            extinct.push(ExtinctSpecies {
+                id: species.id,
+                first_seen: species.first_seen,
+                extinct: world.tick,
+                founder: species.founder.clone(),
+            });
+            false
+        });
+
+        for group in new_groups {
+            self.species.push(Species {
+                id: self.next_id,
+                center: group.center,
+                founder: group.representative.clone(),
+                representative: group.representative,
+                first_seen: world.tick,
+                population: vec![(world.tick, group.members.len())],
+                members: group.members,
+            });
+            self.next_id += 1;
+        }
+    }
+}
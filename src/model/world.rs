@@ -5,28 +5,39 @@ mod brain;
 pub mod chunks;
 pub mod collection;
 pub mod collide;
+pub mod config;
 pub mod gene;
 pub mod genome;
 mod init;
+pub mod map;
 mod math;
 mod muscle;
+pub mod mutation;
 pub mod node;
 pub mod organism;
+pub mod phylogeny;
+pub mod scenario;
 mod sync_mut;
 pub mod tag;
 
 use bone::Bone;
 use chunks::Chunks;
-use collection::Collection;
+use collection::{Collection, GenId};
 use collide::Collider;
+use config::{Config, MapSource};
 use init::random_organisms;
+use map::{Map, OBSTACLE_COLOR};
 use muscle::Muscle;
 use node::Node;
 use organism::Organism;
+use phylogeny::Phylogeny;
+use scenario::Scenario;
+use tag::Tag;
 
 use math::Angle;
-use node::{LifeState, NodeKind};
-use rayon::prelude::ParallelIterator;
+use node::{LifeState, NodeKind, OutputKind, RayChannel, EYE_RANGE_MULT};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use strum::EnumCount;
 
 use crate::model::world::{math::sense_angle_diff, node::SenseKind};
 
@@ -36,6 +47,7 @@ use self::{
 };
 
 pub const MAX_NODE_RADIUS: f32 = 15.0;
+const CHUNK_SIZE: f32 = 40.0;
 const SPLAT_RADIUS_DELTA: f32 = 0.6;
 const SPLAT_MIN_RADIUS: f32 = 2.0;
 
@@ -55,18 +67,46 @@ pub struct World {
     pub size: Vec2,
     pub collider: Collider,
     pub tick: u64,
+    pub config: Config,
+    pub map: Map,
+    pub phylogeny: Phylogeny,
 }
 
 impl World {
-    pub fn new() -> World {
+    pub fn new(config: Config) -> World {
         let mut nodes = Collection::new();
         let mut bones = Collection::new();
         let mut muscles = Collection::new();
         let mut organisms = Collection::new();
         let size = vec2(3375., 3375.);
-        let chunks = Chunks::new(size, 40.0);
-
-        random_organisms(&mut nodes, &mut bones, &mut muscles, &mut organisms, size);
+        let scenario = config.scenario.as_ref().and_then(|path| {
+            Scenario::load(path)
+                .map_err(|err| println!("Couldn't load scenario: {}", err))
+                .ok()
+        });
+        let mut map = match &config.map {
+            MapSource::Empty => Map::empty(size),
+            MapSource::Rocks => Map::rocks(size),
+            MapSource::File(path) => Map::load(path, size).unwrap_or_else(|err| {
+                println!("Couldn't load map: {}", err);
+                Map::empty(size)
+            }),
+        };
+        if let Some(scenario) = &scenario {
+            map = map.with_obstacles(scenario.obstacles(size, CHUNK_SIZE), size);
+        }
+        let chunks = Chunks::new(size, CHUNK_SIZE, &config, &map, scenario.as_ref());
+
+        let mut phylogeny = Phylogeny::new();
+        random_organisms(
+            &mut nodes,
+            &mut bones,
+            &mut muscles,
+            &mut organisms,
+            &mut phylogeny,
+            0,
+            size,
+        );
 
         World {
             nodes,
@@ -77,6 +117,9 @@ impl World {
             chunks,
             collider: Collider::new(size),
             tick: 0,
+            config,
+            map,
+            phylogeny,
         }
     }
     pub fn skip(&mut self, ticks: u64) {
@@ -94,8 +137,16 @@ impl World {
         every(64, self.tick, || self.grow_organisms());
         every(128, self.tick, || self.clear_dead_organisms());
 
-        every(16, self.tick, || self.chunks.update_tide(self.tick));
-        every(16384, self.tick, || self.chunks.update_sun());
+        every(16, self.tick, || {
+            self.chunks.update_tide(self.tick, &self.config.tide)
+        });
+        every(16, self.tick, || {
+            self.chunks
+                .update_sun(self.tick, &self.config.sun, &self.map)
+        });
+        every(256, self.tick, || {
+            self.chunks.update_temperature(self.tick, &self.config.sun)
+        });
 
         if self.nodes.iter().all(|node| !node.is_alive()) {
             println!("All nodes dead");
@@ -104,6 +155,8 @@ impl World {
                 &mut self.bones,
                 &mut self.muscles,
                 &mut self.organisms,
+                &mut self.phylogeny,
+                self.tick,
                 self.size,
             );
         }
@@ -123,6 +176,11 @@ impl World {
         self.muscles.retain(|muscle| !muscle.delete);
     }
     fn update_nodes(&mut self) {
+        // leaves share the light in their chunk, so find how much leaf there is first
+        self.chunks.gather_leaves(self.nodes.iter().filter_map(|node| {
+            let height = node.leaf_height()?;
+            Some((node.pos(), PI * node.radius.powi(2), height))
+        }));
         self.nodes.par_iter_mut().for_each(|node| {
             node.update(self.chunks.get(node.pos()));
         });
@@ -185,32 +243,57 @@ impl World {
 
         // collide nodes with collider
         self.collider
-            .par_collide(&mut self.nodes.view(), collide_pair);
+            .par_collide(&mut self.nodes.view(), |node_1, node_2| {
+                collide_pair(node_1, node_2, &self.config)
+            });
+
+        // keep nodes out of obstacles
+        self.nodes.par_iter_mut().for_each(|node| {
+            let Some(push) = self.map.push_out(node.pos(), node.radius) else { return };
+            *node.pos_mut() += push;
+            // stop moving into the obstacle
+            let normal = push.normalize_or_zero();
+            let vel_into = node.vel.dot(normal);
+            if vel_into < 0. {
+                node.vel -= normal * vel_into;
+            }
+        });
+
+        self.feel_wounds();
 
         // let eye nodes see
-        for i in 0..self.nodes.full_len() {
-            let Some(node) = self.nodes.get_index(i) else {continue};
-            let LifeState::Alive {
-                sense: Some((SenseKind::Eye, SenseCalculate::Calculate(_))),
-                parent: Some((_, angle)),
-                ..
-            } = node.life_state else {continue};
-            let origin = node.pos();
-            let vision = node.radius * 10.0;
-            let dir = angle.to_vec2().normalize_or_zero() * -1. * vision;
-            let Some(seen_node) = self
-                .collider
-                .ray_collides_iter(&self.nodes, origin, dir)
-                .find(|node| ray_collides_circle(origin, dir, node.pos(), node.radius)) else {continue};
-            let dist = origin.distance(seen_node.pos());
+        let sights: Vec<_> = (0..self.nodes.full_len())
+            .into_par_iter()
+            .filter_map(|i| {
+                let node = self.nodes.get_index(i)?;
+                let LifeState::Alive {
+                    sense: Some((SenseKind::Eye, SenseCalculate::Calculate(_))),
+                    parent: Some((_, angle)),
+                    eye: Some(ref eye),
+                    ref tag,
+                    ..
+                } = node.life_state else { return None };
+                let seen: Vec<_> = (0..eye.rays)
+                    .map(|ray| self.see(node, Angle(angle.0 + eye.ray_offset(ray)), tag))
+                    .collect();
+                Some((i, seen))
+            })
+            .collect();
+        for (i, seen) in sights {
             let Some(Node{
                 life_state: LifeState::Alive {
                     sense: Some((_, SenseCalculate::Calculate(ref mut sense))),
+                    eye: Some(ref mut eye),
                     ..
                 },
                 ..
             }) = self.nodes.get_index_mut(i) else {unreachable!()};
-            *sense = 1.0 - dist / vision;
+            // the sense itself is the closest thing any ray sees
+            *sense = seen
+                .iter()
+                .map(|seen| seen[RayChannel::Distance as usize])
+                .fold(0., f32::max);
+            eye.seen = seen;
         }
 
         // keep nodes in bounds
@@ -225,12 +308,89 @@ impl World {
         }
     }
 
+    /// passes wounds from the collide pass on to damage senses and organisms
+    fn feel_wounds(&mut self) {
+        self.organisms.par_iter_mut().for_each(|organism| {
+            organism.lost_nodes = 0;
+        });
+        let mut wounded: Vec<Option<GenId>> = vec![None; self.nodes.full_len()];
+        for (id, node) in self.nodes.iter_with_ids() {
+            if !node.wounded {
+                continue;
+            }
+            wounded[id.index] = Some(id);
+            // eaten nodes are deleted next tick, spiked ones lose a piece when they splat
+            if !node.delete && !node.splat {
+                continue;
+            }
+            let Some(organism_id) = node.owner() else {continue};
+            if let Some(organism) = self.organisms.get_mut(organism_id) {
+                organism.lost_nodes += 1;
+            }
+        }
+        // ids are compared whole so a parent id pointing at a reused slot doesn't pass a wound on
+        let is_wounded = |id: GenId| wounded[id.index] == Some(id);
+
+        // nodes feel their own wounds and the wounds of nodes they share a bone with
+        let mut feel: Vec<bool> = wounded.iter().map(|id| id.is_some()).collect();
+        for (id, node) in self.nodes.iter_with_ids() {
+            let LifeState::Alive { parent: Some((parent_id, _)), .. } = node.life_state else {continue};
+            if is_wounded(id) && self.nodes.get(parent_id).is_some() {
+                feel[parent_id.index] = true;
+            }
+            if is_wounded(parent_id) {
+                feel[id.index] = true;
+            }
+        }
+        for (i, feel) in feel.into_iter().enumerate() {
+            let Some(node) = self.nodes.get_index_mut(i) else {continue};
+            if feel {
+                node.set_sense(SenseKind::Damage, 1.0);
+            }
+            node.wounded = false;
+        }
+    }
+
+    /// cast one ray of an eye, returning what it saw indexed by RayChannel
+    fn see(&self, eye_node: &Node, angle: Angle, tag: &Tag) -> [f32; RayChannel::COUNT] {
+        let origin = eye_node.pos();
+        let vision = eye_node.radius * EYE_RANGE_MULT;
+        let dir = angle.to_vec2().normalize_or_zero() * -1. * vision;
+        let seen_node = self
+            .collider
+            .ray_collides_iter(&self.nodes, origin, dir)
+            .filter(|node| !std::ptr::eq(*node, eye_node))
+            .filter(|node| ray_collides_circle(origin, dir, node.pos(), node.radius))
+            .map(|node| (node, origin.distance(node.pos())))
+            .min_by(|(_, dist_1), (_, dist_2)| dist_1.partial_cmp(dist_2).unwrap());
+        let mut seen = [0.; RayChannel::COUNT];
+        // obstacles hide the nodes behind them
+        let wall_dist = self.map.ray_hit(origin, dir).map(|t| t * vision);
+        if let Some(wall_dist) =
+            wall_dist.filter(|wall_dist| seen_node.is_none_or(|(_, dist)| *wall_dist < dist))
+        {
+            let (red, green, blue) = OBSTACLE_COLOR;
+            seen[RayChannel::Distance as usize] = 1.0 - wall_dist / vision;
+            seen[RayChannel::Red as usize] = red;
+            seen[RayChannel::Green as usize] = green;
+            seen[RayChannel::Blue as usize] = blue;
+        } else if let Some((node, dist)) = seen_node {
+            let (red, green, blue) = node.color();
+            seen[RayChannel::Distance as usize] = 1.0 - (dist / vision).min(1.0);
+            seen[RayChannel::Red as usize] = red;
+            seen[RayChannel::Green as usize] = green;
+            seen[RayChannel::Blue as usize] = blue;
+            seen[RayChannel::Kin as usize] = node.tag_similarity(tag);
+        }
+        seen
+    }
+
     fn think_organsims(&mut self) {
         let view = sync_mut::UnsafeMut::new(self.nodes.view());
         self.organisms.par_iter_mut().for_each(|organism| {
             // this is safe because no 2 organisms share nodes
             let view = unsafe { view.get() };
-            organism.think(view, self.tick);
+            organism.think(view, self.tick, &self.config.sun);
         });
     }
     fn grow_organisms(&mut self) {
@@ -244,12 +404,21 @@ impl World {
             organism.reproduce(&mut self.nodes, &self.collider);
             new_organisms.append(&mut organism.new_organisms);
         }
-        self.organisms.extend(&mut new_organisms);
+        for mut organism in new_organisms {
+            self.phylogeny.record_birth(&mut organism, self.tick);
+            let organism_id = self.organisms.push(organism);
+            self.organisms[organism_id].claim_nodes(organism_id, &mut self.nodes);
+        }
     }
     fn clear_dead_organisms(&mut self) {
         self.organisms.par_iter_mut().for_each(|organism| {
             organism.clear_dead(&self.nodes);
         });
+        for organism in self.organisms.iter() {
+            if organism.delete {
+                self.phylogeny.record_death(organism.lineage, self.tick);
+            }
+        }
         self.organisms.retain(|organism| !organism.delete);
     }
 }
@@ -307,7 +476,7 @@ impl World {
 //         nodes.get_mut(j).unwrap().vel += vel_2;
 //     }
 // }
-fn collide_pair(node_1: &mut Node, node_2: &mut Node) {
+fn collide_pair(node_1: &mut Node, node_2: &mut Node, config: &Config) {
     // if let (PosChange::None, PosChange::None) = (&node_1.pos_change, &node_2.pos_change) {
     //     return;
     // }
@@ -330,8 +499,8 @@ fn collide_pair(node_1: &mut Node, node_2: &mut Node) {
         sense_pair(node_1, node_2);
         sense_pair(node_2, node_1);
 
-        interact_pair(node_1, node_2);
-        interact_pair(node_2, node_1);
+        interact_pair(node_1, node_2, config);
+        interact_pair(node_2, node_1, config);
     }
 }
 fn sense_pair(actor: &mut Node, object: &Node) {
@@ -341,6 +510,7 @@ fn sense_pair(actor: &mut Node, object: &Node) {
             sense: Some((sense_kind, SenseCalculate::Calculate(ref mut value))),
             parent,
             kind,
+            tag,
             ..
         } => {
             use SenseKind::*;
@@ -360,6 +530,7 @@ fn sense_pair(actor: &mut Node, object: &Node) {
                 },
                 CollideRadius => object.radius / MAX_NODE_RADIUS,
                 CollideSpeed => vel_towards(pos, actor.vel, object.pos(), object.vel),
+                CollideKin => object.tag_similarity(tag),
                 _ => return, // if not a collide sense, its handled elsewhere so return
             };
             *value = new_value;
@@ -367,18 +538,46 @@ fn sense_pair(actor: &mut Node, object: &Node) {
         _ => {}
     }
 }
-fn interact_pair(actor: &mut Node, object: &mut Node) {
+/// if the actor should leave the object alone because it's part of itself or close kin
+fn is_immune(actor: &Node, object: &Node, config: &Config) -> bool {
+    let (
+        LifeState::Alive {
+            organism: actor_organism,
+            tag: actor_tag,
+            ..
+        },
+        LifeState::Alive {
+            organism: object_organism,
+            tag: object_tag,
+            ..
+        },
+    ) = (&actor.life_state, &object.life_state) else { return false };
+    if actor_organism.is_some() && actor_organism == object_organism {
+        return true;
+    }
+    config
+        .kin_immunity
+        .is_some_and(|distance| actor_tag.distance(object_tag) <= distance)
+}
+fn interact_pair(actor: &mut Node, object: &mut Node, config: &Config) {
     if actor.delete || object.delete {
         return;
     }
+    if is_immune(actor, object, config) {
+        return;
+    }
     match &mut actor.life_state {
-        LifeState::Alive { kind, .. } => match kind {
+        LifeState::Alive { kind, activate, .. } => match kind {
             NodeKind::Mouth => {
+                if activate[OutputKind::Open as usize] < 0. {
+                    return;
+                }
                 if actor.radius * 0.9 < object.radius {
                     return;
                 }
                 actor.energy += object.energy + object.struct_energy();
                 object.delete = true;
+                object.wounded = true;
             }
             NodeKind::Spike => {
                 let vel_threshold = object.radius.powi(2) / actor.radius.powi(2) * 0.125;
@@ -399,6 +598,7 @@ fn interact_pair(actor: &mut Node, object: &mut Node) {
                     return;
                 }
                 object.splat = true;
+                object.wounded = true;
             }
             _ => {}
         },
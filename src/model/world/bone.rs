@@ -47,6 +47,8 @@ impl Bone {
         // let stroke_amp = vel.dot(facing);
         // let friction = -facing * stroke_amp * 0.8;
 
+        let weights = (parent_node.energy_weight(), child_node.energy_weight());
+
         let (Some(parent_node), Some(child_node)) = nodes.get_2_mut(self.parent_node, self.child_node) else {unreachable!()};
         if !is_zero(distance_diff) {
             *parent_node.pos_mut() += pos_change;
@@ -56,19 +58,9 @@ impl Bone {
         // child_node.accel(friction);
 
         // transfer energy
-        if let (
-            LifeState::Alive {
-                energy_weight: weight_1,
-                ..
-            },
-            LifeState::Alive {
-                energy_weight: weight_2,
-                ..
-            },
-        ) = (&mut parent_node.life_state, &mut child_node.life_state)
-        {
+        if let (Some(weight_1), Some(weight_2)) = weights {
             let energy_ratio = parent_node.energy / child_node.energy;
-            let energy_weight_ratio = *weight_1 / *weight_2;
+            let energy_weight_ratio = weight_1 / weight_2;
             let energy_change = if energy_ratio < energy_weight_ratio {
                 0.1
             } else {
@@ -3,10 +3,12 @@ use std::{collections::HashMap, fmt::Display};
 use super::{
     collection::{CollectionView, GenId},
     gene::{BuildGene, BuildId, Gene, Mutation},
-    node::{Node, NodeKind, ENERGY_LOSS_RATE},
+    mutation::MutationEvent,
+    node::{InputKind, Node, NodeKind, OutputKind, RayChannel, SenseKind, ENERGY_LOSS_RATE},
 };
 use int_enum::IntEnum;
 use rand::random;
+use strum::IntoEnumIterator;
 
 #[derive(Debug, Clone, PartialEq, Eq, Copy)]
 enum ConnectSource {
@@ -34,6 +36,7 @@ enum NeuronsIndexKind {
     Synth,
     Output,
     Hidden,
+    Memory,
 }
 
 #[derive(Debug, Clone)]
@@ -42,6 +45,7 @@ pub struct Neurons {
     synths: Vec<NeuronKind>,
     outputs: Vec<NeuronKind>,
     hiddens: Vec<NeuronKind>,
+    memories: Vec<NeuronKind>,
 }
 
 impl Neurons {
@@ -51,6 +55,7 @@ impl Neurons {
             synths: Vec::new(),
             outputs: Vec::new(),
             hiddens: Vec::new(),
+            memories: Vec::new(),
         }
     }
     fn iter(&self) -> impl Iterator<Item = &NeuronKind> {
@@ -59,6 +64,7 @@ impl Neurons {
             .chain(self.synths.iter())
             .chain(self.outputs.iter())
             .chain(self.hiddens.iter())
+            .chain(self.memories.iter())
     }
     fn iter_index(&self) -> impl Iterator<Item = (NeuronsIndex, &NeuronKind)> {
         use NeuronsIndexKind::*;
@@ -101,10 +107,19 @@ impl Neurons {
                     n,
                 )
             }))
+            .chain(self.memories.iter().enumerate().map(|(i, n)| {
+                (
+                    NeuronsIndex {
+                        index: i,
+                        kind: Memory,
+                    },
+                    n,
+                )
+            }))
     }
 
-    fn add_input(&mut self, id: BuildId) -> NeuronsIndex {
-        self.inputs.push(NeuronKind::Input(id));
+    fn add_input(&mut self, id: BuildId, input: InputKind) -> NeuronsIndex {
+        self.inputs.push(NeuronKind::Input(id, input));
         NeuronsIndex {
             index: self.inputs.len() - 1,
             kind: NeuronsIndexKind::Input,
@@ -117,8 +132,8 @@ impl Neurons {
             kind: NeuronsIndexKind::Synth,
         }
     }
-    fn add_output(&mut self, id: BuildId) -> NeuronsIndex {
-        self.outputs.push(NeuronKind::Output(id));
+    fn add_output(&mut self, id: BuildId, output: OutputKind) -> NeuronsIndex {
+        self.outputs.push(NeuronKind::Output(id, output));
         NeuronsIndex {
             index: self.outputs.len() - 1,
             kind: NeuronsIndexKind::Output,
@@ -131,6 +146,16 @@ impl Neurons {
             kind: NeuronsIndexKind::Hidden,
         }
     }
+    fn add_memory(&mut self, time_constant: f32, latch: bool) -> NeuronsIndex {
+        self.memories.push(NeuronKind::Memory {
+            time_constant,
+            latch,
+        });
+        NeuronsIndex {
+            index: self.memories.len() - 1,
+            kind: NeuronsIndexKind::Memory,
+        }
+    }
     fn vec_from_kind(&self, kind: &NeuronsIndexKind) -> &Vec<NeuronKind> {
         use NeuronsIndexKind::*;
         match kind {
@@ -138,6 +163,7 @@ impl Neurons {
             Synth => &self.synths,
             Output => &self.outputs,
             Hidden => &self.hiddens,
+            Memory => &self.memories,
         }
     }
     fn vec_from_kind_mut(&mut self, kind: &NeuronsIndexKind) -> &mut Vec<NeuronKind> {
@@ -147,6 +173,7 @@ impl Neurons {
             Synth => &mut self.synths,
             Output => &mut self.outputs,
             Hidden => &mut self.hiddens,
+            Memory => &mut self.memories,
         }
     }
     fn random_index(&self, kinds: &[NeuronsIndexKind]) -> Option<NeuronsIndex> {
@@ -180,10 +207,14 @@ impl Neurons {
         }
     }
     fn len(&self) -> usize {
-        self.inputs.len() + self.synths.len() + self.outputs.len() + self.hiddens.len()
+        self.inputs.len()
+            + self.synths.len()
+            + self.outputs.len()
+            + self.hiddens.len()
+            + self.memories.len()
     }
     fn cost_len(&self) -> usize {
-        self.hiddens.len() + self.synths.len()
+        self.hiddens.len() + self.synths.len() + self.memories.len()
     }
     fn index_to_usize(&self, index: NeuronsIndex) -> usize {
         use NeuronsIndexKind::*;
@@ -192,6 +223,13 @@ impl Neurons {
             Synth => self.inputs.len() + index.index,
             Output => self.inputs.len() + self.synths.len() + index.index,
             Hidden => self.inputs.len() + self.synths.len() + self.outputs.len() + index.index,
+            Memory => {
+                self.inputs.len()
+                    + self.synths.len()
+                    + self.outputs.len()
+                    + self.hiddens.len()
+                    + index.index
+            }
         }
     }
 }
@@ -206,6 +244,8 @@ pub struct BrainPlan {
 pub struct Neuron {
     value: f32,
     prev_value: f32,
+    /// if any enabled connect goes into this neuron, unwired outputs leave the node's activate alone
+    wired: bool,
 }
 
 fn activate_hidden(x: f32) -> f32 {
@@ -218,12 +258,59 @@ fn activate_output(x: f32) -> f32 {
     x
 }
 
+/// how strong the summed input of a latch memory has to be to overwrite what it holds
+const LATCH_GATE: f32 = 0.5;
+const MIN_TIME_CONSTANT: f32 = 1.0;
+const MAX_TIME_CONSTANT: f32 = 1024.0;
+const DISTANCE_MIN_CONNECTS: usize = 8;
+
+fn activate_memory(x: f32, state: f32, time_constant: f32, latch: bool) -> f32 {
+    if latch {
+        // gated latch, only stores the input when it is strong enough, otherwise holds
+        if x.abs() > LATCH_GATE {
+            x.tanh()
+        } else {
+            state
+        }
+    } else {
+        // leaky integrator, moves towards the input by 1 / time_constant each tick
+        state + (x.tanh() - state) / time_constant
+    }
+}
+
+fn random_time_constant() -> f32 {
+    // log uniform so that short and long memories are equally likely
+    (MIN_TIME_CONSTANT.ln() + random::<f32>() * (MAX_TIME_CONSTANT.ln() - MIN_TIME_CONSTANT.ln()))
+        .exp()
+}
+
 #[derive(Debug, Clone)]
 pub enum NeuronKind {
-    Input(BuildId),
+    Input(BuildId, InputKind),
     Synth { amp: f32, freq: f32 },
     Hidden,
-    Output(BuildId),
+    Output(BuildId, OutputKind),
+    Memory { time_constant: f32, latch: bool },
+}
+
+impl NeuronKind {
+    /// if the neurons do the same job, inputs and outputs have to belong to the same gene,
+    /// other neurons only have to be the same kind
+    fn same_role(&self, other: &NeuronKind) -> bool {
+        use NeuronKind::*;
+        match (self, other) {
+            (Input(id, input), Input(other_id, other_input)) => {
+                id == other_id && input == other_input
+            }
+            (Output(id, output), Output(other_id, other_output)) => {
+                id == other_id && output == other_output
+            }
+            (Synth { .. }, Synth { .. })
+            | (Hidden, Hidden)
+            | (Memory { .. }, Memory { .. }) => true,
+            _ => false,
+        }
+    }
 }
 
 impl BrainPlan {
@@ -244,20 +331,42 @@ impl BrainPlan {
         }
     }
 
-    fn gene_input_worthy(gene: &BuildGene) -> bool {
-        gene.has_sense == 1 || NodeKind::from_int(gene.node_kind).unwrap().is_input()
-    }
-
-    fn gene_output_worthy(gene: &BuildGene) -> bool {
-        gene.has_muscle == 1 || NodeKind::from_int(gene.node_kind).unwrap().is_output()
+    /// the input channels a gene's node exposes, one input neuron is made for each
+    fn gene_inputs(gene: &BuildGene) -> Vec<InputKind> {
+        if gene.has_sense == 1 && SenseKind::from_int(gene.sense_kind).unwrap() == SenseKind::Eye
+        {
+            // every ray of an eye is its own set of inputs
+            (0..gene.eye_rays)
+                .flat_map(|ray| RayChannel::iter().map(move |channel| InputKind::Ray(ray, channel)))
+                .collect()
+        } else if gene.has_sense == 1 || NodeKind::from_int(gene.node_kind).unwrap().is_input() {
+            vec![InputKind::Sense]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// the output channels a gene's node exposes, one output neuron is made for each
+    fn gene_outputs(gene: &BuildGene) -> Vec<OutputKind> {
+        let muscle = (gene.has_muscle == 1).then_some(OutputKind::Muscle);
+        muscle
+            .into_iter()
+            .chain(
+                NodeKind::from_int(gene.node_kind)
+                    .unwrap()
+                    .outputs()
+                    .iter()
+                    .copied(),
+            )
+            .collect()
     }
 
     fn add_build_gene(&mut self, id: BuildId, gene: &BuildGene) {
-        if Self::gene_input_worthy(gene) {
-            self.add_input(id);
+        for input in Self::gene_inputs(gene) {
+            self.add_input(id, input);
         }
-        if Self::gene_output_worthy(gene) {
-            self.add_output(id);
+        for output in Self::gene_outputs(gene) {
+            self.add_output(id, output);
         }
     }
 
@@ -267,35 +376,69 @@ impl BrainPlan {
     }
 
     fn edit_build_gene(&mut self, id: BuildId, gene: &BuildGene) {
-        let old_input = self.neurons.iter_index().find(|(_, n)| match n {
-            NeuronKind::Input(neuron_id) => *neuron_id == id,
-            _ => false,
-        });
-        match old_input {
-            Some((index, _)) if !Self::gene_input_worthy(gene) => self.delete_neuron(index),
-            None if Self::gene_input_worthy(gene) => self.add_input(id),
-            _ => {}
+        let inputs = Self::gene_inputs(gene);
+        // delete channels the gene lost
+        loop {
+            let Some(delete_neuron_index) = self
+                .neurons
+                .iter_index()
+                .find(|(_, n)| match n {
+                    NeuronKind::Input(neuron_id, input) => {
+                        *neuron_id == id && !inputs.contains(input)
+                    }
+                    _ => false,
+                })
+                .map(|(index, _)| index) else { break };
+            self.delete_neuron(delete_neuron_index);
         }
-        let old_output = self.neurons.iter_index().find(|(_, n)| match n {
-            NeuronKind::Output(neuron_id) => *neuron_id == id,
-            _ => false,
-        });
-        match old_output {
-            Some((index, _)) if !Self::gene_output_worthy(gene) => self.delete_neuron(index),
-            None if Self::gene_output_worthy(gene) => self.add_output(id),
-            _ => {}
+        // add channels the gene gained
+        for input in inputs {
+            let exists = self.neurons.inputs.iter().any(|n| match n {
+                NeuronKind::Input(neuron_id, old_input) => *neuron_id == id && *old_input == input,
+                _ => false,
+            });
+            if !exists {
+                self.add_input(id, input);
+            }
+        }
+        let outputs = Self::gene_outputs(gene);
+        // delete channels the gene lost
+        loop {
+            let Some(delete_neuron_index) = self
+                .neurons
+                .iter_index()
+                .find(|(_, n)| match n {
+                    NeuronKind::Output(neuron_id, output) => {
+                        *neuron_id == id && !outputs.contains(output)
+                    }
+                    _ => false,
+                })
+                .map(|(index, _)| index) else { break };
+            self.delete_neuron(delete_neuron_index);
+        }
+        // add channels the gene gained
+        for output in outputs {
+            let exists = self.neurons.outputs.iter().any(|n| match n {
+                NeuronKind::Output(neuron_id, old_output) => {
+                    *neuron_id == id && *old_output == output
+                }
+                _ => false,
+            });
+            if !exists {
+                self.add_output(id, output);
+            }
         }
     }
 
-    fn add_input(&mut self, id: BuildId) {
-        let index = self.neurons.add_input(id);
+    fn add_input(&mut self, id: BuildId, input: InputKind) {
+        let index = self.neurons.add_input(id, input);
         if random::<f32>() > 0.75 {
             self.add_random_connect(Some(ConnectSource::Neuron(index)), None);
         }
     }
 
-    fn add_output(&mut self, id: BuildId) {
-        let index = self.neurons.add_output(id);
+    fn add_output(&mut self, id: BuildId, output: OutputKind) {
+        let index = self.neurons.add_output(id, output);
         if random::<f32>() > 0.75 {
             self.add_random_connect(None, Some(index));
         }
@@ -307,7 +450,7 @@ impl BrainPlan {
                 .neurons
                 .iter_index()
                 .find(|(_, n)| match n {
-                    NeuronKind::Input(neuron_id) => *neuron_id == id,
+                    NeuronKind::Input(neuron_id, _) => *neuron_id == id,
                     _ => false,
                 })
                 .map(|(index, _)| index) else { break };
@@ -321,7 +464,7 @@ impl BrainPlan {
                 .neurons
                 .iter_index()
                 .find(|(_, n)| match n {
-                    NeuronKind::Output(neuron_id) => *neuron_id == id,
+                    NeuronKind::Output(neuron_id, _) => *neuron_id == id,
                     _ => false,
                 })
                 .map(|(index, _)| index) else { break };
@@ -329,20 +472,27 @@ impl BrainPlan {
         }
     }
 
-    fn mutate_add_input_wave(&mut self) {
+    fn mutate_add_input_wave(&mut self) -> Option<MutationEvent> {
         let amp = random::<f32>() * 2.0 - 1.0;
         let freq = random::<f32>() * 0.1 + 0.01;
         self.neurons.add_synth(amp, freq);
+        Some(MutationEvent::AddSynth { amp, freq })
     }
 
-    fn add_random_connect(&mut self, from: Option<ConnectSource>, to: Option<NeuronsIndex>) {
+    fn add_random_connect(
+        &mut self,
+        from: Option<ConnectSource>,
+        to: Option<NeuronsIndex>,
+    ) -> Option<MutationEvent> {
         use NeuronsIndexKind::*;
         let from = match from {
             Some(source) => source,
             None => {
                 if random::<f32>() > 0.4 {
-                    let Some(index) = self.neurons.random_index(&[Input, Synth, Hidden]) else { return };
-                    ConnectSource::Neuron(index)
+                    ConnectSource::Neuron(
+                        self.neurons
+                            .random_index(&[Input, Synth, Hidden, Memory])?,
+                    )
                 } else {
                     ConnectSource::Bias
                 }
@@ -350,10 +500,7 @@ impl BrainPlan {
         };
         let to = match to {
             Some(index) => index,
-            None => match self.neurons.random_index(&[Hidden, Output]) {
-                Some(index) => index,
-                None => return,
-            },
+            None => self.neurons.random_index(&[Hidden, Output, Memory])?,
         };
         let weight = random::<f32>() * 4.0 - 2.0;
         let enabled = true;
@@ -363,15 +510,24 @@ impl BrainPlan {
             weight,
             enabled,
         });
+        Some(MutationEvent::AddConnect {
+            from: match from {
+                ConnectSource::Neuron(index) => Some(self.neurons.index_to_usize(index)),
+                ConnectSource::Bias => None,
+            },
+            to: self.neurons.index_to_usize(to),
+            weight,
+        })
     }
-    fn mutate_add_connect(&mut self) {
-        self.add_random_connect(None, None);
+    fn mutate_add_connect(&mut self) -> Option<MutationEvent> {
+        self.add_random_connect(None, None)
     }
-    fn mutate_add_neuron(&mut self) {
+    fn mutate_add_neuron(&mut self) -> Option<MutationEvent> {
         if self.connects.is_empty() {
-            return;
+            return None;
         }
-        let connect = self.connects[random::<usize>() % self.connects.len()].clone();
+        let connect_index = random::<usize>() % self.connects.len();
+        let connect = self.connects[connect_index].clone();
 
         let index = self.neurons.add_hidden();
         let new_connect_1 = Connect {
@@ -389,13 +545,71 @@ impl BrainPlan {
 
         self.connects.push(new_connect_1);
         self.connects.push(new_connect_2);
+        Some(MutationEvent::AddHidden {
+            connect: connect_index,
+        })
     }
-    fn mutate_delete_connect(&mut self) {
+    fn mutate_add_memory(&mut self) -> Option<MutationEvent> {
         if self.connects.is_empty() {
-            return;
+            return None;
         }
-        self.connects
-            .swap_remove(random::<usize>() % self.connects.len());
+        let connect_index = random::<usize>() % self.connects.len();
+        let connect = self.connects[connect_index].clone();
+
+        // memories are spliced into a connect like hidden neurons, but read back their own value
+        let time_constant = random_time_constant();
+        let latch = random::<f32>() < 0.25;
+        let index = self.neurons.add_memory(time_constant, latch);
+        self.connects.push(Connect {
+            from: connect.from,
+            to: index,
+            weight: 1.0,
+            enabled: true,
+        });
+        self.connects.push(Connect {
+            from: ConnectSource::Neuron(index),
+            to: connect.to,
+            weight: connect.weight,
+            enabled: connect.enabled,
+        });
+        Some(MutationEvent::AddMemory {
+            connect: connect_index,
+            time_constant,
+            latch,
+        })
+    }
+    fn mutate_time_constant(&mut self) -> Option<MutationEvent> {
+        let index = self.neurons.random_index(&[NeuronsIndexKind::Memory])?;
+        let neuron = self.neurons.index_to_usize(index);
+        let NeuronKind::Memory {
+            ref mut time_constant,
+            ref mut latch,
+        } = self.neurons.memories[index.index] else { unreachable!() };
+        if random::<f32>() < 0.1 {
+            *latch = !*latch;
+            Some(MutationEvent::ToggleLatch {
+                neuron,
+                latch: *latch,
+            })
+        } else {
+            // shift multiplicatively so that short and long memories change at the same rate
+            let old = *time_constant;
+            *time_constant = (*time_constant * (random::<f32>() * 2.0 - 1.0).exp())
+                .clamp(MIN_TIME_CONSTANT, MAX_TIME_CONSTANT);
+            Some(MutationEvent::TimeConstant {
+                neuron,
+                old,
+                new: *time_constant,
+            })
+        }
+    }
+    fn mutate_delete_connect(&mut self) -> Option<MutationEvent> {
+        if self.connects.is_empty() {
+            return None;
+        }
+        let connect = random::<usize>() % self.connects.len();
+        self.connects.swap_remove(connect);
+        Some(MutationEvent::DeleteConnect { connect })
     }
     fn delete_neuron(&mut self, index: NeuronsIndex) {
         // remove all connects to and from this neuron
@@ -416,40 +630,64 @@ impl BrainPlan {
             }
         }
     }
-    fn mutate_delete_neuron(&mut self) {
-        self.neurons
-            .random_index(&[NeuronsIndexKind::Synth, NeuronsIndexKind::Hidden])
-            .map(|index| self.delete_neuron(index));
+    fn mutate_delete_neuron(&mut self) -> Option<MutationEvent> {
+        let index = self.neurons.random_index(&[
+            NeuronsIndexKind::Synth,
+            NeuronsIndexKind::Hidden,
+            NeuronsIndexKind::Memory,
+        ])?;
+        let neuron = self.neurons.index_to_usize(index);
+        self.delete_neuron(index);
+        Some(MutationEvent::DeleteNeuron { neuron })
     }
-    fn mutate_enable_disable(&mut self) {
+    fn mutate_enable_disable(&mut self) -> Option<MutationEvent> {
         if self.connects.is_empty() {
-            return;
+            return None;
         }
-        let len = self.connects.len();
-        let connect = &mut self.connects[random::<usize>() % len];
+        let index = random::<usize>() % self.connects.len();
+        let connect = &mut self.connects[index];
         connect.enabled = !connect.enabled;
+        Some(MutationEvent::EnableConnect {
+            connect: index,
+            enabled: connect.enabled,
+        })
     }
-    fn mutate_weight_shift(&mut self) {
+    fn mutate_weight_shift(&mut self) -> Option<MutationEvent> {
         if self.connects.is_empty() {
-            return;
+            return None;
         }
-        let len = self.connects.len();
-        let connect = &mut self.connects[random::<usize>() % len];
+        let index = random::<usize>() % self.connects.len();
+        let connect = &mut self.connects[index];
+        let old = connect.weight;
         connect.weight += random::<f32>() * 2.0 - 1.0;
+        Some(MutationEvent::ShiftWeight {
+            connect: index,
+            old,
+            new: connect.weight,
+        })
     }
-    fn mutate_weight_random(&mut self) {
+    fn mutate_weight_random(&mut self) -> Option<MutationEvent> {
         if self.connects.is_empty() {
-            return;
+            return None;
         }
-        let len = self.connects.len();
-        let connect = &mut self.connects[random::<usize>() % len];
+        let index = random::<usize>() % self.connects.len();
+        let connect = &mut self.connects[index];
+        let old = connect.weight;
         connect.weight = random::<f32>() * 2.0 - 1.0;
-    }
-    pub fn mutate(&mut self) {
+        Some(MutationEvent::RandomWeight {
+            connect: index,
+            old,
+            new: connect.weight,
+        })
+    }
+    /// returns the mutation that was made, None if the one picked had nothing to change
+    pub fn mutate(&mut self) -> Option<MutationEvent> {
         let mutations = [
             Self::mutate_add_input_wave,
             Self::mutate_add_connect,
             Self::mutate_add_neuron,
+            Self::mutate_add_memory,
+            Self::mutate_time_constant,
             Self::mutate_delete_connect,
             Self::mutate_delete_neuron,
             Self::mutate_enable_disable,
@@ -457,11 +695,75 @@ impl BrainPlan {
             Self::mutate_weight_random,
         ];
         let mutation = mutations[random::<usize>() % mutations.len()];
-        mutation(self);
+        mutation(self)
     }
     pub fn cost(&self) -> f32 {
         self.neurons.cost_len() as f32 * ENERGY_LOSS_RATE * 0.015625
     }
+    /// feeds the shape of the brain and every connect to the hasher
+    pub fn hash_connects(&self, state: &mut impl std::hash::Hasher) {
+        state.write_u64(self.neurons.iter().count() as u64);
+        for connect in &self.connects {
+            let from = match connect.from {
+                ConnectSource::Neuron(index) => self.neurons.index_to_usize(index) as u64 + 1,
+                ConnectSource::Bias => 0,
+            };
+            state.write_u64(from);
+            state.write_u64(self.neurons.index_to_usize(connect.to) as u64);
+            state.write_u32(connect.weight.to_bits());
+            state.write_u8(connect.enabled as u8);
+        }
+    }
+    fn source_neuron(&self, source: ConnectSource) -> Option<&NeuronKind> {
+        match source {
+            ConnectSource::Neuron(index) => Some(self.neuron(index)),
+            ConnectSource::Bias => None,
+        }
+    }
+    fn neuron(&self, index: NeuronsIndex) -> &NeuronKind {
+        &self.neurons.vec_from_kind(&index.kind)[index.index]
+    }
+    /// how different two brains are, from the enabled connects that have no counterpart in the
+    /// other brain and the weight differences of the ones that do.
+    /// small brains count as DISTANCE_MIN_CONNECTS big so one change doesn't look huge
+    pub fn distance(&self, other: &BrainPlan) -> f32 {
+        let enabled = |plan: &BrainPlan| -> Vec<(Option<NeuronKind>, NeuronKind, f32)> {
+            plan.connects
+                .iter()
+                .filter(|connect| connect.enabled)
+                .map(|connect| {
+                    (
+                        plan.source_neuron(connect.from).cloned(),
+                        plan.neuron(connect.to).clone(),
+                        connect.weight,
+                    )
+                })
+                .collect()
+        };
+        let same_source = |a: &Option<NeuronKind>, b: &Option<NeuronKind>| match (a, b) {
+            (Some(a), Some(b)) => a.same_role(b),
+            (None, None) => true,
+            _ => false,
+        };
+        let connects = enabled(self);
+        let mut other_connects: Vec<_> = enabled(other).into_iter().map(Some).collect();
+        let mut matched = 0;
+        let mut weight_difference = 0.;
+        for (from, to, weight) in connects.iter() {
+            let counterpart = other_connects.iter_mut().find(|other| {
+                other.as_ref().is_some_and(|(other_from, other_to, _)| {
+                    same_source(from, other_from) && to.same_role(other_to)
+                })
+            });
+            let Some(counterpart) = counterpart else { continue };
+            let (_, _, other_weight) = counterpart.take().unwrap();
+            matched += 1;
+            weight_difference += (weight - other_weight).abs();
+        }
+        let most = connects.len().max(other_connects.len()).max(DISTANCE_MIN_CONNECTS);
+        let unmatched = connects.len() + other_connects.len() - 2 * matched;
+        unmatched as f32 / most as f32 + 0.4 * weight_difference / matched.max(1) as f32
+    }
 }
 
 impl Display for BrainPlan {
@@ -499,13 +801,18 @@ impl Brain {
             neurons: if connections_to_outputs == 0 {
                 NeuronCalculate::Skip
             } else {
-                NeuronCalculate::Calculate(vec![
-                    Neuron {
-                        value: 0.0,
-                        prev_value: 0.0,
-                    };
-                    plan.neurons.len()
-                ])
+                NeuronCalculate::Calculate(
+                    (0..plan.neurons.len())
+                        .map(|i| Neuron {
+                            value: 0.0,
+                            prev_value: 0.0,
+                            wired: plan
+                                .connects
+                                .iter()
+                                .any(|c| c.enabled && plan.neurons.index_to_usize(c.to) == i),
+                        })
+                        .collect(),
+                )
             },
         }
     }
@@ -522,17 +829,17 @@ impl Brain {
             NeuronCalculate::Calculate(neurons) => neurons,
         };
         // reset all neurons, and set input neurons to their input values
-        let get_node_sense = |id: BuildId| {
+        let get_node_input = |id: BuildId, input: InputKind| {
             build_id_map
                 .get(&id)
                 // safe because no 2 organisms share nodes
-                .and_then(|node_id| nodes.get(*node_id).and_then(|node| node.sense().map(|s| s)))
+                .and_then(|node_id| nodes.get(*node_id).and_then(|node| node.input(input)))
                 .unwrap_or(0.0)
         };
-        for (Neuron { value, prev_value }, kind) in neurons.iter_mut().zip(plan.neurons.iter()) {
+        for (Neuron { value, prev_value, .. }, kind) in neurons.iter_mut().zip(plan.neurons.iter()) {
             *prev_value = *value;
             *value = match kind {
-                NeuronKind::Input(id) => get_node_sense(id.clone()),
+                NeuronKind::Input(id, input) => get_node_input(*id, *input),
                 NeuronKind::Synth { amp, freq } => {
                     *amp * (2.0 * std::f32::consts::PI * tick as f32 * *freq).sin()
                 }
@@ -553,19 +860,34 @@ impl Brain {
             *to += from * weight;
         }
 
-        for (Neuron { value, .. }, kind) in neurons.iter_mut().zip(plan.neurons.iter()) {
+        for (
+            Neuron {
+                value,
+                prev_value,
+                wired,
+            },
+            kind,
+        ) in neurons.iter_mut().zip(plan.neurons.iter())
+        {
             let new_value = match kind {
                 NeuronKind::Input(..) | NeuronKind::Synth { .. } => *value, // dont set activate for input neurons
                 NeuronKind::Hidden => activate_hidden(*value),
                 NeuronKind::Output(..) => activate_output(*value),
+                // prev_value is the state the memory held last tick
+                NeuronKind::Memory {
+                    time_constant,
+                    latch,
+                } => activate_memory(*value, *prev_value, *time_constant, *latch),
             };
             *value = new_value;
 
-            if let NeuronKind::Output(id) = kind {
+            if let (NeuronKind::Output(id, output), true) = (kind, *wired) {
                 let activate = *value;
                 // safe because no 2 organisms share nodes
                 build_id_map.get(&id).and_then(|node_id| {
-                    nodes.get_mut(*node_id).map(|node| node.activate(activate))
+                    nodes
+                        .get_mut(*node_id)
+                        .map(|node| node.activate(*output, activate))
                 });
             }
         }
@@ -2,12 +2,162 @@ use itertools::iproduct;
 use nannou::prelude::*;
 use noise::{NoiseFn, SuperSimplex};
 
+use super::config::Config;
+use super::map::Map;
+use super::math::Angle;
+use super::scenario::Scenario;
+
 #[derive(Debug, Clone)]
 pub struct Chunk {
     pub sun: f32,
     pub tide: Vec2,
+    /// a current that never changes, added to the tide
+    pub current: Vec2,
+    /// how deep the water is, from 0 to 1, deeper water is colder
+    pub sea_depth: f32,
+    /// roughly from 0 to 1, warm at the equator in the middle of the world and in summer
+    pub temperature: f32,
+    /// how much leaf area the sun can fully light
+    pub light_budget: f32,
+    /// total area of the leaves in the chunk this tick
+    pub leaf_area: f32,
+    /// sum of each leaf's area times its height, for the average height
+    leaf_area_height: f32,
+}
+
+impl Chunk {
+    /// the fraction of full sun a leaf gets, shared by area, with higher leaves taking more
+    pub fn light_share(&self, height: f32) -> f32 {
+        if self.leaf_area <= self.light_budget {
+            return 1.;
+        }
+        let mean_height = self.leaf_area_height / self.leaf_area;
+        let height_share = if mean_height > 0. {
+            height / mean_height
+        } else {
+            1.
+        };
+        (self.light_budget / self.leaf_area * height_share).min(1.)
+    }
 }
 pub const TIDE_MULT: f32 = 0.05;
+const SUN_SPOTS: usize = 6;
+/// how long the shadows of obstacles are at noon
+const SHADOW_LENGTH: f32 = 80.0;
+/// how much light is left in the shadow of an obstacle
+const SHADE: f32 = 0.3;
+/// temperature at the poles, the top and bottom edges of the world
+const POLE_TEMPERATURE: f32 = 0.1;
+/// temperature at the equator, the middle of the world
+const EQUATOR_TEMPERATURE: f32 = 0.9;
+/// how much colder the deepest water is
+const DEPTH_COOLING: f32 = 0.3;
+/// how much warmer midsummer is and colder midwinter is
+const SEASON_WARMING: f32 = 0.15;
+
+/// how the light changes over time
+#[derive(Debug, Clone)]
+pub struct SunCycle {
+    /// ticks from one sunrise to the next
+    pub day_length: u64,
+    /// ticks from one summer to the next
+    pub year_length: u64,
+    /// how much brighter summer days are and darker winter days are, from 0 to 1
+    pub season_strength: f32,
+    /// light left at midnight, from 0 to 1
+    pub night_light: f32,
+    /// how far sun spots move per tick
+    pub drift_speed: f32,
+}
+
+impl Default for SunCycle {
+    fn default() -> Self {
+        SunCycle {
+            day_length: 4096,
+            year_length: 65536,
+            season_strength: 0.3,
+            night_light: 0.1,
+            drift_speed: 0.05,
+        }
+    }
+}
+
+impl SunCycle {
+    /// how far through the day the tick is, from 0 to 1, starting at sunrise
+    pub fn day_phase(&self, tick: u64) -> f32 {
+        (tick % self.day_length) as f32 / self.day_length as f32
+    }
+    /// 1 at noon, -1 at midnight
+    pub fn time_of_day(&self, tick: u64) -> f32 {
+        (self.day_phase(tick) * TAU).sin()
+    }
+    /// 1 at midsummer, -1 at midwinter
+    pub fn season(&self, tick: u64) -> f32 {
+        ((tick % self.year_length) as f32 / self.year_length as f32 * TAU).sin()
+    }
+    /// direction from the ground to the sun, rising in the east and setting in the west
+    pub fn sun_dir(&self, tick: u64) -> Vec2 {
+        // the day is the first half of the phase, the sun stays set in the west at night
+        let daytime = (self.day_phase(tick) * 2.).min(1.);
+        Angle(daytime * PI).to_vec2()
+    }
+    /// how much of the sun spots' light reaches the world
+    pub fn daylight(&self, tick: u64) -> f32 {
+        let day = self.time_of_day(tick).max(0.);
+        let season = 1. + self.season_strength * self.season(tick);
+        (self.night_light + (1. - self.night_light) * day) * season
+    }
+}
+
+/// how the tide flows, strengths are multiples of TIDE_MULT
+#[derive(Debug, Clone)]
+pub enum TideMode {
+    /// swirls that slowly change over time
+    Noise { scale: f32, speed: f32 },
+    /// a circle around the middle of the world
+    Vortex { strength: f32 },
+    /// the same flow everywhere
+    Laminar { angle: f32, strength: f32 },
+    /// horizontal bands flowing in alternating directions
+    Shear { bands: u32, strength: f32 },
+    Off,
+}
+
+impl Default for TideMode {
+    fn default() -> Self {
+        TideMode::Noise {
+            scale: 0.01,
+            speed: 0.005,
+        }
+    }
+}
+
+impl TideMode {
+    /// parses the name of a mode with its default parameters
+    pub fn from_name(name: &str) -> Option<TideMode> {
+        Some(match name {
+            "noise" => TideMode::default(),
+            "vortex" => TideMode::Vortex { strength: 1.0 },
+            "laminar" => TideMode::Laminar {
+                angle: 0.0,
+                strength: 0.5,
+            },
+            "shear" => TideMode::Shear {
+                bands: 4,
+                strength: 0.5,
+            },
+            "off" => TideMode::Off,
+            _ => return None,
+        })
+    }
+}
+
+/// a bright area that wanders around the world
+#[derive(Debug, Clone)]
+pub struct SunSpot {
+    pub pos: Point2,
+    pub vel: Vec2,
+}
 
 #[derive(Debug, Clone)]
 pub struct Chunks {
@@ -15,94 +165,182 @@ pub struct Chunks {
     pub world_size: Vec2,
     pub grid_size: (usize, usize),
     pub noise: (SuperSimplex, SuperSimplex),
+    pub sun_spots: Vec<SunSpot>,
+    /// sun for each chunk from a scenario, used instead of the sun spots
+    pub sun_map: Option<Vec<f32>>,
+    sun_tick: u64,
 }
 // TODO add jet stream node
 // TODO add gene duplicating
 // TODO make energy transfer lossy
 impl Chunks {
-    pub fn new(size: Vec2, cell_size: f32) -> Self {
+    pub fn new(
+        size: Vec2,
+        cell_size: f32,
+        config: &Config,
+        map: &Map,
+        scenario: Option<&Scenario>,
+    ) -> Self {
         let grid_width = (size.x / cell_size).ceil() as usize;
         let grid_height = (size.y / cell_size).ceil() as usize;
 
-        let grid: Vec<_> = iproduct!(0..grid_height, 0..grid_width)
+        let mut grid: Vec<_> = iproduct!(0..grid_height, 0..grid_width)
             .map(|_| Chunk {
                 sun: 0.,
                 tide: vec2(0., 0.),
+                current: vec2(0., 0.),
+                sea_depth: 0.,
+                temperature: 0.,
+                light_budget: cell_size.powi(2),
+                leaf_area: 0.,
+                leaf_area_height: 0.,
             })
             .collect();
-        // for _ in 0..8 {
-        //     // choose random sun position
-        //     let sun_pos: Point2 = (random_range(0., size.x), random_range(0., size.y)).into();
-        //     let sun_radius = size.x / 4.0;
-        //     for (y, x) in iproduct!(0..grid_height, 0..grid_width) {
-        //         let pos = vec2(x as f32 * cell_size, y as f32 * cell_size);
-        //         // sun is from 0 to 1
-        //         let sun = 1.0 - (sun_pos.distance(pos) / sun_radius).clamp(0.0, 1.0);
-        //         grid[y * grid_width + x].sun += sun;
-        //     }
-        // }
-        // let middle = vec2(size.x / 2.0, size.y / 2.0);
-        // for (y, x) in iproduct!(0..grid_height, 0..grid_width) {
-        //     let pos = vec2(x as f32 * cell_size, y as f32 * cell_size);
-        //     // make it go in a circle around the middle (so rotate 90 degrees)
-        //     let tide = (pos - middle).rotate(PI / 2.0).normalize();
-        //     grid[y * grid_width + x].tide = tide * TIDE_MULT;
-        // }
+        let depth_noise = SuperSimplex::new(random_range(40, 60));
+        for (y, x) in iproduct!(0..grid_height, 0..grid_width) {
+            let pos = vec2(x as f32, y as f32) * cell_size * 0.001;
+            let depth = depth_noise.get([pos.x as f64, pos.y as f64]) as f32;
+            grid[y * grid_width + x].sea_depth = (depth + 1.) / 2.;
+        }
+        let sun_map = scenario.map(|scenario| {
+            let grid_size = (grid_width, grid_height);
+            for (chunk, current) in grid.iter_mut().zip(scenario.current(grid_size)) {
+                chunk.current = current;
+            }
+            scenario.sun(grid_size)
+        });
 
         let noise = (
             SuperSimplex::new(random_range(0, 20)),
             SuperSimplex::new(random_range(20, 40)),
         );
 
+        let sun_spots = (0..SUN_SPOTS)
+            .map(|_| SunSpot {
+                pos: vec2(random_range(0., size.x), random_range(0., size.y)),
+                vel: Angle(random_range(0., TAU)).to_vec2(),
+            })
+            .collect();
+
         let mut ret = Chunks {
             grid,
             world_size: size,
             grid_size: (grid_width, grid_height),
             noise,
+            sun_spots,
+            sun_map,
+            sun_tick: 0,
         };
-        ret.update_tide(0);
-        ret.update_sun();
+        ret.update_tide(0, &config.tide);
+        ret.update_sun(0, &config.sun, map);
+        ret.update_temperature(0, &config.sun);
         ret
     }
-    pub fn get(&self, pos: Vec2) -> &Chunk {
+    fn index(&self, pos: Vec2) -> usize {
         let y = ((pos.y / self.world_size.y * self.grid_size.1 as f32) as usize)
             .clamp(0, self.grid_size.1 - 1);
         let x = ((pos.x / self.world_size.x * self.grid_size.0 as f32) as usize)
             .clamp(0, self.grid_size.0 - 1);
-        &self.grid[y * self.grid_size.0 + x]
+        y * self.grid_size.0 + x
     }
-    pub fn update_tide(&mut self, tick: u64) {
+    pub fn get(&self, pos: Vec2) -> &Chunk {
+        &self.grid[self.index(pos)]
+    }
+    /// adds up the leaves in each chunk, given their position, area and height
+    pub fn gather_leaves(&mut self, leaves: impl Iterator<Item = (Point2, f32, f32)>) {
+        for chunk in self.grid.iter_mut() {
+            chunk.leaf_area = 0.;
+            chunk.leaf_area_height = 0.;
+        }
+        for (pos, area, height) in leaves {
+            let i = self.index(pos);
+            self.grid[i].leaf_area += area;
+            self.grid[i].leaf_area_height += area * height;
+        }
+    }
+    pub fn update_tide(&mut self, tick: u64, mode: &TideMode) {
         let cell_size = self.world_size.x / self.grid_size.0 as f32;
-        let scale = 0.01;
-        let time = tick as f64 * 0.005;
+        let middle = self.world_size / 2.;
         for (x, y) in iproduct!(0..self.grid_size.0, 0..self.grid_size.1) {
-            let pos = vec2(x as f32 * cell_size, y as f32 * cell_size) * scale;
-            let tide = vec2(
-                self.noise.0.get([pos.x as f64, pos.y as f64, time]) as f32,
-                self.noise.1.get([pos.x as f64, pos.y as f64, time]) as f32,
-            ) * TIDE_MULT;
-            self.grid[y * self.grid_size.0 + x].tide = tide;
+            let pos = vec2(x as f32 * cell_size, y as f32 * cell_size);
+            let tide = match *mode {
+                TideMode::Noise { scale, speed } => {
+                    let pos = pos * scale;
+                    let time = tick as f64 * speed as f64;
+                    vec2(
+                        self.noise.0.get([pos.x as f64, pos.y as f64, time]) as f32,
+                        self.noise.1.get([pos.x as f64, pos.y as f64, time]) as f32,
+                    )
+                }
+                TideMode::Vortex { strength } => {
+                    // go in a circle around the middle, so rotate 90 degrees
+                    (pos - middle).perp().normalize_or_zero() * strength
+                }
+                TideMode::Laminar { angle, strength } => Angle(angle).to_vec2() * strength,
+                TideMode::Shear { bands, strength } => {
+                    let band = pos.y / self.world_size.y * bands as f32 * PI;
+                    vec2(band.sin(), 0.) * strength
+                }
+                TideMode::Off => vec2(0., 0.),
+            } * TIDE_MULT;
+            let chunk = &mut self.grid[y * self.grid_size.0 + x];
+            chunk.tide = tide + chunk.current;
         }
     }
-    pub fn update_sun(&mut self) {
+    pub fn update_temperature(&mut self, tick: u64, sun_cycle: &SunCycle) {
+        let season = sun_cycle.season(tick) * SEASON_WARMING;
         for (y, x) in iproduct!(0..self.grid_size.1, 0..self.grid_size.0) {
-            self.grid[y * self.grid_size.0 + x].sun = 0.;
+            // 0 at the poles, 1 at the equator
+            let latitude = 1. - (y as f32 / (self.grid_size.1 - 1).max(1) as f32 * 2. - 1.).abs();
+            let chunk = &mut self.grid[y * self.grid_size.0 + x];
+            chunk.temperature = POLE_TEMPERATURE
+                + (EQUATOR_TEMPERATURE - POLE_TEMPERATURE) * latitude
+                - DEPTH_COOLING * chunk.sea_depth
+                + season;
         }
-        let cell_size = self.world_size.x / self.grid_size.0 as f32;
-        for _ in 0..6 {
-            // choose random sun position
-            let sun_pos: Point2 = (
-                random_range(0., self.world_size.x),
-                random_range(0., self.world_size.y),
-            )
-                .into();
-            let sun_radius = self.world_size.x / 4.0;
-            for (y, x) in iproduct!(0..self.grid_size.1, 0..self.grid_size.0) {
-                let pos = vec2(x as f32 * cell_size, y as f32 * cell_size);
-                // sun is from 0 to 1
-                let sun = 1.0 - (sun_pos.distance(pos) / sun_radius).clamp(0.0, 1.0);
-                self.grid[y * self.grid_size.0 + x].sun += sun;
+    }
+    fn drift_sun_spots(&mut self, tick: u64, sun_cycle: &SunCycle) {
+        let elapsed = tick.saturating_sub(self.sun_tick) as f32;
+        self.sun_tick = tick;
+        for spot in &mut self.sun_spots {
+            // wander slowly, and bounce off the edges of the world
+            let turn = random_range(-0.1, 0.1) * elapsed.sqrt();
+            spot.vel = Angle(Angle::from_vec2(spot.vel).0 + turn).to_vec2();
+            spot.pos += spot.vel * sun_cycle.drift_speed * elapsed;
+            if !(0.0..self.world_size.x).contains(&spot.pos.x) {
+                spot.vel.x *= -1.;
+            }
+            if !(0.0..self.world_size.y).contains(&spot.pos.y) {
+                spot.vel.y *= -1.;
             }
+            spot.pos = spot.pos.clamp(vec2(0., 0.), self.world_size);
+        }
+    }
+    pub fn update_sun(&mut self, tick: u64, sun_cycle: &SunCycle, map: &Map) {
+        self.drift_sun_spots(tick, sun_cycle);
+        let daylight = sun_cycle.daylight(tick);
+        // shadows get longer as the sun gets lower
+        let sun_height = sun_cycle.time_of_day(tick).max(0.25);
+        let shadow = sun_cycle.sun_dir(tick) * SHADOW_LENGTH / sun_height;
+        let cell_size = self.world_size.x / self.grid_size.0 as f32;
+        let sun_radius = self.world_size.x / 4.0;
+        for (y, x) in iproduct!(0..self.grid_size.1, 0..self.grid_size.0) {
+            let pos = vec2(x as f32 * cell_size, y as f32 * cell_size);
+            let sun: f32 = match &self.sun_map {
+                Some(sun_map) => sun_map[y * self.grid_size.0 + x],
+                None => self
+                    .sun_spots
+                    .iter()
+                    // each spot is from 0 to 1
+                    .map(|spot| 1.0 - (spot.pos.distance(pos) / sun_radius).clamp(0.0, 1.0))
+                    .sum(),
+            };
+            let shade = if map.ray_hit(pos, shadow).is_some() {
+                SHADE
+            } else {
+                1.0
+            };
+            self.grid[y * self.grid_size.0 + x].sun = sun * daylight * shade;
         }
     }
 }
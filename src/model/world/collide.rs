@@ -270,7 +270,7 @@ impl Collider {
     pub fn par_collide(
         &mut self,
         nodes: &mut CollectionView<Node>,
-        collide_fn: fn(&mut Node, &mut Node),
+        collide_fn: impl Fn(&mut Node, &mut Node) + Sync,
     ) {
         self.update(nodes);
         let nodes_slice = super::sync_mut::UnsafeMutSlice::new(nodes.get_mut_slice());
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use super::chunks::{SunCycle, TideMode};
+
+/// where the obstacles of the world come from
+#[derive(Debug, Clone, Default)]
+pub enum MapSource {
+    #[default]
+    Empty,
+    /// clumps of rocks generated from noise
+    Rocks,
+    /// a map file, see Map::load
+    File(PathBuf),
+}
+
+/// settings for the simulation that aren't evolved
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// mouths and spikes also leave alone organisms with tags at most this far from their own,
+    /// None to only leave alone their own organism
+    pub kin_immunity: Option<f32>,
+    pub sun: SunCycle,
+    pub tide: TideMode,
+    pub map: MapSource,
+    /// an image to take the sun, current and obstacles from, see Scenario
+    pub scenario: Option<PathBuf>,
+}
+
+impl Config {
+    /// reads settings from the command line, `--map <file>` loads a map file, `--rocks` generates rocks
+    /// `--scenario <image>` loads a scenario image and `--tide <mode>` picks how the tide flows
+    pub fn from_args() -> Config {
+        let mut config = Config::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--map" => match args.next() {
+                    Some(path) => config.map = MapSource::File(path.into()),
+                    None => println!("--map needs a file"),
+                },
+                "--rocks" => config.map = MapSource::Rocks,
+                "--scenario" => match args.next() {
+                    Some(path) => config.scenario = Some(path.into()),
+                    None => println!("--scenario needs an image"),
+                },
+                "--tide" => match args.next().as_deref().and_then(TideMode::from_name) {
+                    Some(tide) => config.tide = tide,
+                    None => println!("--tide needs one of noise, vortex, laminar, shear or off"),
+                },
+                _ => println!("Unknown argument: {}", arg),
+            }
+        }
+        config
+    }
+}
@@ -3,8 +3,11 @@ use super::brain::BrainPlan;
 use super::collection::GenId;
 use super::math::Angle;
 use super::muscle::Muscle;
-use super::node::{Node, NodeKind, SenseKind};
+use super::mutation::{MutationEvent, MutationRates};
+use super::node::{Eye, Node, NodeKind, SenseKind, MAX_LIFESPAN};
+use super::tag::Tag;
 use int_enum::IntEnum;
+use std::fmt::{Display, Write};
 
 use nannou::prelude::*;
 use strum::EnumCount;
@@ -13,14 +16,20 @@ mod macros;
 use super::MAX_NODE_RADIUS;
 use macros::{count_fields, make_gene_struct, replace_expr};
 
+const DISTANCE_MIN_GENES: usize = 4;
+
 make_gene_struct!(pub BuildGene {
     node_radius: f32 = 2.0..MAX_NODE_RADIUS,
     node_energy_weight: f32 = 1.0..10.0,
     node_kind: u8 = 0..(NodeKind::COUNT),
-    node_lifespan: u32 = 256..32_768,
+    node_lifespan: u32 = 256..MAX_LIFESPAN,
+    optimal_temperature: f32 = 0.0..1.0,
+    temperature_tolerance: f32 = 0.0..0.5,
 
     has_sense: u8 = 0..2,
     sense_kind: u8 = 0..(SenseKind::COUNT),
+    eye_rays: u8 = 1..6,
+    eye_fov: f32 = 0.0..PI,
 
     bone_length: f32 = 5.0..30.0,
 
@@ -34,13 +43,16 @@ make_gene_struct!(pub BuildGene {
 });
 
 impl BuildGene {
+    #[allow(clippy::too_many_arguments)]
     pub fn build_node(
         &self,
         pos: Point2,
         gene_index: Option<usize>,
         energy: f32,
         parent: Option<(GenId, Point2)>,
+        depth: u32,
         sense_calculate: bool,
+        tag: &Tag,
     ) -> Node {
         let kind = NodeKind::from_int(self.node_kind).unwrap();
         let energy_weight = self.node_energy_weight;
@@ -54,6 +66,10 @@ impl BuildGene {
                 sense_calculate,
             ))
         };
+        let eye = match sense_kind {
+            Some((SenseKind::Eye, _)) => Some(Eye::new(self.eye_rays, self.eye_fov)),
+            _ => None,
+        };
         let parent = parent.map(|(id, parent_pos)| {
             let angle = Angle::from_vec2(pos - parent_pos);
             (id, angle)
@@ -68,7 +84,12 @@ impl BuildGene {
             gene_index,
             parent,
             lifespan,
+            depth,
+            self.bone_length,
+            (self.optimal_temperature, self.temperature_tolerance),
             sense_kind,
+            eye,
+            tag.clone(),
         )
     }
     pub fn build_bone(&self, parent_node: GenId, child_node: GenId, min_length: f32) -> Bone {
@@ -84,6 +105,29 @@ impl BuildGene {
         Some(Muscle::new(joint_id, node_1, node_2, length, strength))
     }
 
+    /// the fields that matter most, on one line
+    pub fn summary(&self) -> String {
+        let mut summary = match NodeKind::from_int(self.node_kind) {
+            Ok(kind) => format!("{:?}", kind),
+            Err(_) => "?".to_string(),
+        };
+        write!(
+            summary,
+            " r{:.1} bone {:.0}",
+            self.node_radius, self.bone_length
+        )
+        .unwrap();
+        if self.has_sense == 1 {
+            if let Ok(kind) = SenseKind::from_int(self.sense_kind) {
+                write!(summary, " {:?}", kind).unwrap();
+            }
+        }
+        if self.has_muscle == 1 {
+            summary.push_str(" muscle");
+        }
+        summary
+    }
+
     pub fn energy_cost(&self) -> f32 {
         let mut cost = 0.0;
         cost += self.node_radius.powi(3) / 50.0; // up to 67.5, 7 is 6.86, 5 is 2.5, 10 is 20
@@ -125,18 +169,26 @@ impl Gene {
         }
     }
 
-    pub fn mutate_one(&mut self) {
+    /// returns the name of the field that changed, with its old and new value
+    pub fn mutate_one(&mut self) -> Option<(&'static str, f32, f32)> {
         match self {
             Gene::Build((gene, _)) => gene.mutate_one(),
-            Gene::Repeat => {}
-            Gene::Up => {}
+            Gene::Repeat => None,
+            Gene::Up => None,
         }
     }
-    pub fn mutate_one_gradual(&mut self) {
+    /// returns the name of the field that changed, with its old and new value
+    pub fn mutate_one_gradual(&mut self, step: f32) -> Option<(&'static str, f32, f32)> {
         match self {
-            Gene::Build((gene, _)) => gene.mutate_one_gradual(),
-            Gene::Repeat => {}
-            Gene::Up => {}
+            Gene::Build((gene, _)) => gene.mutate_one_gradual(step),
+            Gene::Repeat => None,
+            Gene::Up => None,
+        }
+    }
+    fn node_kind(&self) -> Option<NodeKind> {
+        match self {
+            Gene::Build((gene, _)) => NodeKind::from_int(gene.node_kind).ok(),
+            Gene::Repeat | Gene::Up => None,
         }
     }
 }
@@ -190,7 +242,7 @@ impl BodyPlan {
         genes.push(egg_gene);
 
         let mut ret = BodyPlan { genes };
-        ret.mutate(brain);
+        ret.mutate(brain, &MutationRates::default());
 
         ret
     }
@@ -226,8 +278,13 @@ impl BodyPlan {
 
     //     ret
     // }
-    pub fn mutate(&mut self, brain: &mut BrainPlan) {
-        let mutation_count = random_range(1, 4);
+    pub fn mutate(
+        &mut self,
+        brain: &mut BrainPlan,
+        rates: &MutationRates,
+    ) -> Vec<MutationEvent> {
+        let mut events = Vec::new();
+        let mutation_count = rates.random_body_count();
         for _ in 0..mutation_count {
             let (i, mutation) = match self.genes.len() {
                 0 => (0, Mutation::Add),
@@ -238,24 +295,46 @@ impl BodyPlan {
                 Mutation::Add => {
                     let new_gene = Gene::random();
                     brain.mutate_gene(mutation, &new_gene);
+                    events.push(MutationEvent::AddGene {
+                        index: i,
+                        kind: new_gene.node_kind(),
+                    });
                     self.genes.insert(i, new_gene);
                 }
                 Mutation::Delete => {
                     let rem_gene = self.genes.remove(i);
                     brain.mutate_gene(mutation, &rem_gene);
+                    events.push(MutationEvent::DeleteGene { index: i });
                 }
                 Mutation::Edit => {
-                    self.genes[i].mutate_one();
+                    let change = self.genes[i].mutate_one();
                     brain.mutate_gene(mutation, &self.genes[i]);
+                    if let Some((field, old, new)) = change {
+                        events.push(MutationEvent::EditGene {
+                            index: i,
+                            field,
+                            old,
+                            new,
+                        });
+                    }
                 }
                 Mutation::EditGradual => {
-                    self.genes[i].mutate_one_gradual();
+                    let change = self.genes[i].mutate_one_gradual(rates.gradual_step);
                     brain.mutate_gene(mutation, &self.genes[i]);
+                    if let Some((field, old, new)) = change {
+                        events.push(MutationEvent::EditGradual {
+                            index: i,
+                            field,
+                            old,
+                            new,
+                        });
+                    }
                 }
             };
         }
 
         self.make_valid();
+        events
     }
     pub fn get(&self, index: usize) -> Option<&Gene> {
         self.genes.get(index)
@@ -348,37 +427,103 @@ impl BodyPlan {
     pub fn len(&self) -> usize {
         self.genes.len()
     }
+    /// feeds every gene to the hasher, build ids are left out since they're random
+    pub fn hash_genes(&self, state: &mut impl std::hash::Hasher) {
+        for gene in &self.genes {
+            match gene {
+                Gene::Build((gene, _)) => {
+                    state.write_u8(0);
+                    gene.hash_fields(state);
+                }
+                Gene::Repeat => state.write_u8(1),
+                Gene::Up => state.write_u8(2),
+            }
+        }
+    }
+    /// edit distance between the gene lists, divided by the longer length, where build genes
+    /// from the same original gene line up and cost how much their fields differ.
+    /// short lists count as DISTANCE_MIN_GENES long so one change doesn't look huge
+    pub fn distance(&self, other: &BodyPlan) -> f32 {
+        let substitute = |a: &Gene, b: &Gene| match (a, b) {
+            (Gene::Build((a, a_id)), Gene::Build((b, b_id))) if a_id == b_id => a.distance(b),
+            (Gene::Repeat, Gene::Repeat) | (Gene::Up, Gene::Up) => 0.,
+            _ => 1.,
+        };
+        // one row of the edit distance table at a time
+        let mut row: Vec<f32> = (0..=other.genes.len()).map(|j| j as f32).collect();
+        for (i, gene) in self.genes.iter().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i as f32 + 1.;
+            for (j, other_gene) in other.genes.iter().enumerate() {
+                let cost = (diagonal + substitute(gene, other_gene))
+                    .min(row[j] + 1.)
+                    .min(row[j + 1] + 1.);
+                diagonal = row[j + 1];
+                row[j + 1] = cost;
+            }
+        }
+        let longest = self.genes.len().max(other.genes.len()).max(DISTANCE_MIN_GENES);
+        row[other.genes.len()] / longest as f32
+    }
+    /// each gene with how many builds it's nested in
+    fn nested(&self) -> impl Iterator<Item = (usize, &Gene)> {
+        self.genes.iter().scan(0usize, |depth, gene| {
+            let nesting = match gene {
+                Gene::Build(_) => {
+                    *depth += 1;
+                    *depth - 1
+                }
+                Gene::Repeat => *depth,
+                Gene::Up => {
+                    *depth = depth.saturating_sub(1);
+                    *depth
+                }
+            };
+            Some((nesting, gene))
+        })
+    }
+    /// one short line per gene, indented by how deep it builds
+    pub fn outline(&self) -> Vec<String> {
+        self.nested()
+            .map(|(depth, gene)| {
+                let indent = "  ".repeat(depth);
+                match gene {
+                    Gene::Build((gene, _)) => format!("{indent}{} {{", gene.summary()),
+                    Gene::Repeat => format!("{indent}Repeat"),
+                    Gene::Up => format!("{indent}}} Up"),
+                }
+            })
+            .collect()
+    }
 }
 
-use std::fmt::Display;
-
 impl Display for BodyPlan {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut depth = 0;
-        for gene in &self.genes {
-            let mut tabs = depth;
+        // builds left open at the end are closed after the last gene
+        let mut open = 0;
+        for (depth, gene) in self.nested() {
             let s = match gene {
                 Gene::Build(gene) => {
-                    depth += 1;
+                    open = depth + 1;
                     format!("Build {} {{", gene.0)
                 }
                 Gene::Repeat => {
+                    open = depth;
                     format!("Repeat")
                 }
                 Gene::Up => {
-                    depth -= 1;
-                    tabs -= 1;
+                    open = depth;
                     format!("}} Up")
                 }
             };
 
-            let tabs = "\t".repeat(0.max(tabs) as usize);
+            let tabs = "\t".repeat(depth);
 
             writeln!(f, "{tabs}{s}")?;
         }
-        while depth > 0 {
-            depth -= 1;
-            let tabs = "\t".repeat(depth as usize);
+        while open > 0 {
+            open -= 1;
+            let tabs = "\t".repeat(open);
             writeln!(f, "{tabs}}}")?;
         }
         Ok(())
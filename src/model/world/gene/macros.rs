@@ -15,38 +15,61 @@ macro_rules! make_gene_struct {
                     $( $var: random_range($lower as $ty, $upper as $ty) ),*
                 }
             }
+            /// returns the name of the field that changed, with its old and new value
             #[allow(unused_assignments)]
-            fn mutate_one(&mut self) {
+            fn mutate_one(&mut self) -> Option<(&'static str, f32, f32)> {
                 let mut threshold = 0.0;
                 let delta = 1.0 / count_fields!($( $var ),*) as f32;
                 let rand = random::<f32>();
                 $( if rand < threshold {
+                    let old = self.$var as f32;
                     self.$var = random_range($lower as $ty, $upper as $ty);
-                    return;
+                    return Some((stringify!($var), old, self.$var as f32));
                 } else {
                     threshold += delta;
                 } )*
+                None
             }
+            /// returns the name of the field that changed, with its old and new value
             #[allow(unused_assignments)]
-            fn mutate_one_gradual(&mut self) {
+            fn mutate_one_gradual(&mut self, step: f32) -> Option<(&'static str, f32, f32)> {
                 let mut threshold = 0.0;
                 let delta = 1.0 / count_fields!($( $var ),*) as f32;
                 let rand = random::<f32>();
                 $( if rand < threshold {
+                    let old = self.$var as f32;
+                    let step = (($upper as $ty - $lower as $ty) as f32 * step) as $ty;
                     if random() {
-                        self.$var += ($upper as $ty - $lower as $ty) / 10 as $ty;
+                        self.$var += step;
                     } else {
-                        self.$var -= ($upper as $ty - $lower as $ty) / 10 as $ty;
+                        // unsigned fields can't go below zero before the clamp
+                        self.$var = if self.$var > $lower as $ty + step {
+                            self.$var - step
+                        } else {
+                            $lower as $ty
+                        };
                     }
                     self.$var= self.$var.clamp($lower as $ty, $upper as $ty);
                     // make range exclusive
                     if self.$var == $upper as $ty {
                         self.$var = random_range($lower as $ty, $upper as $ty);
                     }
-                    return;
+                    return Some((stringify!($var), old, self.$var as f32));
                 } else {
                     threshold += delta;
                 } )*
+                None
+            }
+            /// feeds every field to the hasher, as the bits of its value as an f32
+            pub fn hash_fields(&self, state: &mut impl std::hash::Hasher) {
+                $( state.write_u32((self.$var as f32).to_bits()); )*
+            }
+            /// the mean difference of the fields, each scaled by its range, from 0 to 1
+            pub fn distance(&self, other: &Self) -> f32 {
+                let mut sum = 0.0;
+                $( sum += (self.$var as f32 - other.$var as f32).abs()
+                    / ($upper as $ty - $lower as $ty) as f32; )*
+                sum / count_fields!($( $var ),*) as f32
             }
             #[allow(dead_code)]
             fn cross_over(&self, other: &Self) -> Self {
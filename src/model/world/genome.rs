@@ -2,6 +2,7 @@ use std::fmt::{Display, Formatter};
 
 use super::brain::BrainPlan;
 use super::gene::BodyPlan;
+use super::mutation::{MutationEvent, MutationRates};
 use super::tag::Tag;
 use nannou::prelude::random;
 
@@ -10,6 +11,7 @@ pub struct Genome {
     pub body: BodyPlan,
     pub brain: BrainPlan,
     pub tag: Tag,
+    pub rates: MutationRates,
 }
 
 impl Genome {
@@ -17,7 +19,12 @@ impl Genome {
         let mut brain = BrainPlan::new();
         let body = BodyPlan::random_plant(&mut brain);
         let tag = Tag::random();
-        Genome { body, brain, tag }
+        Genome {
+            body,
+            brain,
+            tag,
+            rates: MutationRates::default(),
+        }
     }
     // pub fn random_animal() -> Genome {
     //     let mut brain = BrainPlan::new();
@@ -25,17 +32,33 @@ impl Genome {
     //     let tag = Tag::random();
     //     Genome { body, brain, tag }
     // }
-    pub fn mutate(&mut self) {
-        let r = random::<f32>();
+    /// how different the genes are, ignoring the tag, 0 for identical genomes
+    pub fn distance(&self, other: &Genome) -> f32 {
+        self.body.distance(&other.body) + self.brain.distance(&other.brain)
+    }
+    /// feeds the genes to the hasher, ignoring the tag and the mutation rates
+    pub fn hash_genes(&self, state: &mut impl std::hash::Hasher) {
+        self.body.hash_genes(state);
+        state.write_u8(0xff);
+        self.brain.hash_connects(state);
+    }
+    /// returns the mutations that were made, not counting the tag
+    ///
+    /// the rates are mutated too, but only take effect from the next generation
+    pub fn mutate(&mut self) -> Vec<MutationEvent> {
+        let rates = self.rates.clone();
         self.tag.mutate();
-        if r < 0.5 {
-            self.body.mutate(&mut self.brain);
-            self.brain.mutate();
-        } else if r < 0.75 {
-            self.body.mutate(&mut self.brain);
+        let mut events = if random::<f32>() < rates.both_chance {
+            let mut events = self.body.mutate(&mut self.brain, &rates);
+            events.extend(self.brain.mutate());
+            events
+        } else if random::<f32>() < rates.body_chance {
+            self.body.mutate(&mut self.brain, &rates)
         } else {
-            self.brain.mutate();
-        }
+            self.brain.mutate().into_iter().collect()
+        };
+        events.push(self.rates.mutate());
+        events
     }
 }
 
@@ -43,8 +66,8 @@ impl Display for Genome {
     fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
         write!(
             f,
-            "Genome:\nBodyPlan:\n{}\nBrainPlan:\n{}",
-            self.body, self.brain
+            "Genome:\nMutationRates: {}\nBodyPlan:\n{}\nBrainPlan:\n{}",
+            self.rates, self.body, self.brain
         )
     }
 }
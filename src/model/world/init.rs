@@ -4,6 +4,7 @@ use super::genome::Genome;
 use super::muscle::Muscle;
 use super::node::Node;
 use super::organism::Organism;
+use super::phylogeny::Phylogeny;
 use nannou::prelude::*;
 
 pub fn random_organisms(
@@ -11,12 +12,17 @@ pub fn random_organisms(
     _bones: &mut Collection<Bone>,
     _muscles: &mut Collection<Muscle>,
     organisms: &mut Collection<Organism>,
+    phylogeny: &mut Phylogeny,
+    tick: u64,
     size: Vec2,
 ) {
     for _ in 0..((size.x * size.y / 1500.) as usize) {
         let genome = Genome::random_plant();
         let pos = vec2(random_range(0., size.x), random_range(0., size.y));
-        organisms.push(Organism::new(pos, genome, 20., nodes));
+        let mut organism = Organism::new(pos, genome, 20., nodes);
+        phylogeny.record_birth(&mut organism, tick);
+        let organism_id = organisms.push(organism);
+        organisms[organism_id].claim_nodes(organism_id, nodes);
     }
 }
 
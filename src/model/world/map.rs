@@ -0,0 +1,281 @@
+use std::io;
+use std::path::Path;
+
+use itertools::iproduct;
+use nannou::prelude::*;
+use noise::{NoiseFn, SuperSimplex};
+
+use super::MAX_NODE_RADIUS;
+
+const MAP_CELL_SIZE: f32 = 40.0;
+pub const OBSTACLE_COLOR: (f32, f32, f32) = (0.35, 0.3, 0.25);
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// returns the fraction along the ray where it crosses the segment
+fn ray_hits_segment(origin: Point2, dir: Vec2, a: Point2, b: Point2) -> Option<f32> {
+    let edge = b - a;
+    let denom = cross(dir, edge);
+    if denom.abs() < 0.000_001 {
+        return None;
+    }
+    let t = cross(a - origin, edge) / denom;
+    let u = cross(a - origin, dir) / denom;
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)).then_some(t)
+}
+
+fn closest_on_segment(pos: Point2, a: Point2, b: Point2) -> Point2 {
+    let edge = b - a;
+    let t = ((pos - a).dot(edge) / edge.length_squared()).clamp(0.0, 1.0);
+    if !t.is_finite() {
+        return a;
+    }
+    a + edge * t
+}
+
+/// a static shape that nodes can't pass through
+#[derive(Debug, Clone)]
+pub enum Obstacle {
+    Circle { center: Point2, radius: f32 },
+    Polygon(Vec<Point2>),
+}
+
+impl Obstacle {
+    fn edges(points: &[Point2]) -> impl Iterator<Item = (Point2, Point2)> + '_ {
+        points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)| (*a, *b))
+    }
+    pub fn contains(&self, pos: Point2) -> bool {
+        match self {
+            Obstacle::Circle { center, radius } => center.distance_squared(pos) < radius.powi(2),
+            Obstacle::Polygon(points) => {
+                // even odd rule
+                Obstacle::edges(points)
+                    .filter(|(a, b)| {
+                        (a.y > pos.y) != (b.y > pos.y)
+                            && pos.x < (b.x - a.x) * (pos.y - a.y) / (b.y - a.y) + a.x
+                    })
+                    .count()
+                    % 2
+                    == 1
+            }
+        }
+    }
+    /// the lowest and highest corners of a box around the obstacle
+    fn bounds(&self) -> (Point2, Point2) {
+        match self {
+            Obstacle::Circle { center, radius } => (
+                *center - vec2(*radius, *radius),
+                *center + vec2(*radius, *radius),
+            ),
+            Obstacle::Polygon(points) => points.iter().fold(
+                (vec2(f32::MAX, f32::MAX), vec2(f32::MIN, f32::MIN)),
+                |(min, max), point| (min.min(*point), max.max(*point)),
+            ),
+        }
+    }
+    /// how far a circle has to move to stop overlapping, None if it doesn't overlap
+    pub fn push_out(&self, pos: Point2, radius: f32) -> Option<Vec2> {
+        match self {
+            Obstacle::Circle {
+                center,
+                radius: obstacle_radius,
+            } => {
+                let diff = pos - *center;
+                let min_dist = radius + obstacle_radius;
+                let dist_squared = diff.length_squared();
+                if dist_squared >= min_dist.powi(2) {
+                    return None;
+                }
+                let dist = dist_squared.sqrt();
+                let dir = diff.try_normalize().unwrap_or(vec2(1., 0.));
+                Some(dir * (min_dist - dist))
+            }
+            Obstacle::Polygon(points) => {
+                let closest = Obstacle::edges(points)
+                    .map(|(a, b)| closest_on_segment(pos, a, b))
+                    .min_by(|a, b| {
+                        a.distance_squared(pos)
+                            .partial_cmp(&b.distance_squared(pos))
+                            .unwrap()
+                    })?;
+                let diff = closest - pos;
+                if self.contains(pos) {
+                    // move to the edge, then far enough out of it
+                    let dir = diff.try_normalize().unwrap_or(vec2(1., 0.));
+                    return Some(diff + dir * radius);
+                }
+                let dist = diff.length();
+                if dist >= radius {
+                    return None;
+                }
+                let dir = (-diff).try_normalize().unwrap_or(vec2(1., 0.));
+                Some(dir * (radius - dist))
+            }
+        }
+    }
+    /// the fraction along the ray where it first hits, None if it misses
+    pub fn ray_hit(&self, origin: Point2, dir: Vec2) -> Option<f32> {
+        if self.contains(origin) {
+            return Some(0.);
+        }
+        match self {
+            Obstacle::Circle { center, radius } => {
+                let diff = origin - *center;
+                let a = dir.dot(dir);
+                let b = 2.0 * diff.dot(dir);
+                let c = diff.dot(diff) - radius.powi(2);
+                let discriminant = b.powi(2) - 4.0 * a * c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let t = (-b - discriminant.sqrt()) / (2.0 * a);
+                (0.0..=1.0).contains(&t).then_some(t)
+            }
+            Obstacle::Polygon(points) => Obstacle::edges(points)
+                .filter_map(|(a, b)| ray_hits_segment(origin, dir, a, b))
+                .min_by(|a, b| a.partial_cmp(b).unwrap()),
+        }
+    }
+}
+
+/// the static terrain of the world
+#[derive(Debug, Clone)]
+pub struct Map {
+    pub obstacles: Vec<Obstacle>,
+    /// indices of the obstacles that are close to each cell
+    cells: Vec<Vec<usize>>,
+    grid_size: (usize, usize),
+}
+
+impl Map {
+    pub fn new(obstacles: Vec<Obstacle>, world_size: Vec2) -> Map {
+        let grid_size = (
+            (world_size.x / MAP_CELL_SIZE).ceil() as usize,
+            (world_size.y / MAP_CELL_SIZE).ceil() as usize,
+        );
+        let mut map = Map {
+            obstacles,
+            cells: vec![Vec::new(); grid_size.0 * grid_size.1],
+            grid_size,
+        };
+        for (i, obstacle) in map.obstacles.iter().enumerate() {
+            // pad so that nodes touching the obstacle from a neighboring cell still find it
+            let (min, max) = obstacle.bounds();
+            let padding = vec2(MAX_NODE_RADIUS, MAX_NODE_RADIUS);
+            let (min_x, min_y) = map.pos_to_cell(min - padding);
+            let (max_x, max_y) = map.pos_to_cell(max + padding);
+            for (x, y) in iproduct!(min_x..=max_x, min_y..=max_y) {
+                map.cells[y * grid_size.0 + x].push(i);
+            }
+        }
+        map
+    }
+    /// the same map with more obstacles
+    pub fn with_obstacles(self, obstacles: Vec<Obstacle>, world_size: Vec2) -> Map {
+        Map::new(self.obstacles.into_iter().chain(obstacles).collect(), world_size)
+    }
+    pub fn empty(world_size: Vec2) -> Map {
+        Map::new(Vec::new(), world_size)
+    }
+    /// scatters clumps of round rocks where noise is high
+    pub fn rocks(world_size: Vec2) -> Map {
+        let noise = SuperSimplex::new(random_range(0, 20));
+        let spacing = MAP_CELL_SIZE * 1.5;
+        let scale = 0.002;
+        let obstacles = iproduct!(
+            0..(world_size.x / spacing) as usize,
+            0..(world_size.y / spacing) as usize
+        )
+        .filter_map(|(x, y)| {
+            let center = vec2(x as f32, y as f32) * spacing
+                + vec2(random_range(0., spacing), random_range(0., spacing));
+            let height = noise.get([(center.x * scale) as f64, (center.y * scale) as f64]) as f32;
+            (height > 0.3).then_some(Obstacle::Circle {
+                center,
+                radius: spacing * (0.4 + height),
+            })
+        })
+        .collect();
+        Map::new(obstacles, world_size)
+    }
+    /// reads a map file, where each line is either
+    /// `circle x y radius` or `polygon x1 y1 x2 y2 x3 y3 ...`, and `#` starts a comment
+    pub fn load(path: &Path, world_size: Vec2) -> io::Result<Map> {
+        let invalid = |line: usize, message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), line + 1, message),
+            )
+        };
+        let mut obstacles = Vec::new();
+        for (i, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            let Some(shape) = words.next() else { continue };
+            let numbers = words
+                .map(|word| word.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid(i, "expected a number"))?;
+            let obstacle = match (shape, numbers.as_slice()) {
+                ("circle", [x, y, radius]) => Obstacle::Circle {
+                    center: vec2(*x, *y),
+                    radius: *radius,
+                },
+                ("circle", _) => return Err(invalid(i, "circle needs x, y and radius")),
+                ("polygon", numbers) if numbers.len() >= 6 && numbers.len() % 2 == 0 => {
+                    Obstacle::Polygon(
+                        numbers
+                            .chunks(2)
+                            .map(|point| vec2(point[0], point[1]))
+                            .collect(),
+                    )
+                }
+                ("polygon", _) => return Err(invalid(i, "polygon needs at least 3 points")),
+                _ => return Err(invalid(i, "unknown shape")),
+            };
+            obstacles.push(obstacle);
+        }
+        Ok(Map::new(obstacles, world_size))
+    }
+    fn pos_to_cell(&self, pos: Point2) -> (usize, usize) {
+        let x = ((pos.x / MAP_CELL_SIZE).max(0.) as usize).min(self.grid_size.0 - 1);
+        let y = ((pos.y / MAP_CELL_SIZE).max(0.) as usize).min(self.grid_size.1 - 1);
+        (x, y)
+    }
+    /// indices of obstacles that could be inside the box
+    fn nearby(&self, min: Point2, max: Point2) -> Vec<usize> {
+        let (min_x, min_y) = self.pos_to_cell(min);
+        let (max_x, max_y) = self.pos_to_cell(max);
+        let mut nearby: Vec<usize> = iproduct!(min_x..=max_x, min_y..=max_y)
+            .flat_map(|(x, y)| self.cells[y * self.grid_size.0 + x].iter().copied())
+            .collect();
+        nearby.sort_unstable();
+        nearby.dedup();
+        nearby
+    }
+    /// how far a circle has to move to stop overlapping any obstacle, None if it doesn't
+    pub fn push_out(&self, pos: Point2, radius: f32) -> Option<Vec2> {
+        let (x, y) = self.pos_to_cell(pos);
+        let push = self.cells[y * self.grid_size.0 + x]
+            .iter()
+            .filter_map(|i| self.obstacles[*i].push_out(pos, radius))
+            .fold(vec2(0., 0.), |sum, push| sum + push);
+        (push != vec2(0., 0.)).then_some(push)
+    }
+    /// the fraction along the ray where it first hits an obstacle, None if it misses
+    pub fn ray_hit(&self, origin: Point2, dir: Vec2) -> Option<f32> {
+        if self.obstacles.is_empty() {
+            return None;
+        }
+        let end = origin + dir;
+        self.nearby(origin.min(end), origin.max(end))
+            .into_iter()
+            .filter_map(|i| self.obstacles[i].ray_hit(origin, dir))
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+    }
+}
@@ -46,6 +46,11 @@ impl std::ops::Sub for Angle {
     }
 }
 
+/// maps 0..infinity to 0..1, reaching 0.5 at half
+pub fn saturate(x: f32, half: f32) -> f32 {
+    x / (x + half)
+}
+
 pub fn is_zero(f: f32) -> bool {
     f.abs() < 0.0001
 }
@@ -1,5 +1,5 @@
 use super::collection::{CollectionView, GenId};
-use super::node::{Node, MUSCLE_ENERGY_RATE};
+use super::node::{Node, OutputKind, MUSCLE_ENERGY_RATE};
 
 #[derive(Debug, Clone)]
 pub struct Muscle {
@@ -42,7 +42,8 @@ impl Muscle {
         //     None => self.angle,
         // };
         let min_len = node_1.radius + node_2.radius;
-        let real_len = (self.len * joint_node.unwrap_activate().clamp(0.1, 2.0)).max(min_len);
+        let real_len = (self.len * joint_node.unwrap_activate(OutputKind::Muscle).clamp(0.1, 2.0))
+            .max(min_len);
         let dist_diff = node_1.pos().distance(node_2.pos()) - real_len;
         let accel_mag = dist_diff * 0.0625 * self.strength;
 
@@ -0,0 +1,334 @@
+use std::fmt::Display;
+
+use nannou::prelude::*;
+
+use super::node::NodeKind;
+
+/// how a genome mutates, kept in the genome and mutated with it so that evolvability can evolve
+#[derive(Debug, Clone, PartialEq)]
+pub struct MutationRates {
+    /// chance a child's genome is mutated at all
+    pub chance: f32,
+    /// most body mutations made at once, rounded down
+    pub body_count: f32,
+    /// chance both the body and the brain mutate, instead of only one
+    pub both_chance: f32,
+    /// chance it's the body when only one mutates
+    pub body_chance: f32,
+    /// how far a gradual edit moves a field, as a fraction of its range
+    pub gradual_step: f32,
+}
+
+const CHANCE_RANGE: (f32, f32) = (0.05, 1.0);
+const BODY_COUNT_RANGE: (f32, f32) = (1.0, 8.0);
+const GRADUAL_STEP_RANGE: (f32, f32) = (0.01, 0.5);
+
+impl Default for MutationRates {
+    fn default() -> Self {
+        MutationRates {
+            chance: 0.5,
+            body_count: 3.0,
+            both_chance: 0.5,
+            body_chance: 0.5,
+            gradual_step: 0.1,
+        }
+    }
+}
+
+impl MutationRates {
+    /// how many body mutations to make this time, from 1 to body_count
+    pub fn random_body_count(&self) -> usize {
+        random_range(1.0, self.body_count + 1.0) as usize
+    }
+    /// nudges one of the rates, staying inside its bounds
+    pub fn mutate(&mut self) -> MutationEvent {
+        // the body/brain split chances shift, the others scale so small values change as easily
+        let (field, value, range, scales) = match random_range(0, 5) {
+            0 => ("chance", &mut self.chance, CHANCE_RANGE, true),
+            1 => ("body_count", &mut self.body_count, BODY_COUNT_RANGE, true),
+            2 => ("both_chance", &mut self.both_chance, (0.0, 1.0), false),
+            3 => ("body_chance", &mut self.body_chance, (0.0, 1.0), false),
+            _ => (
+                "gradual_step",
+                &mut self.gradual_step,
+                GRADUAL_STEP_RANGE,
+                true,
+            ),
+        };
+        let new = if scales {
+            *value * (random::<f32>() * 2.0 - 1.0).exp()
+        } else {
+            *value + random::<f32>() * 0.2 - 0.1
+        }
+        .clamp(range.0, range.1);
+        let old = *value;
+        *value = new;
+        MutationEvent::Rate { field, old, new }
+    }
+    /// the average of each rate, None if there are no rates
+    pub fn mean<'a>(rates: impl Iterator<Item = &'a MutationRates>) -> Option<MutationRates> {
+        let mut sum = MutationRates {
+            chance: 0.0,
+            body_count: 0.0,
+            both_chance: 0.0,
+            body_chance: 0.0,
+            gradual_step: 0.0,
+        };
+        let mut count = 0;
+        for rates in rates {
+            sum.chance += rates.chance;
+            sum.body_count += rates.body_count;
+            sum.both_chance += rates.both_chance;
+            sum.body_chance += rates.body_chance;
+            sum.gradual_step += rates.gradual_step;
+            count += 1;
+        }
+        if count == 0 {
+            return None;
+        }
+        let count = count as f32;
+        Some(MutationRates {
+            chance: sum.chance / count,
+            body_count: sum.body_count / count,
+            both_chance: sum.both_chance / count,
+            body_chance: sum.body_chance / count,
+            gradual_step: sum.gradual_step / count,
+        })
+    }
+}
+
+impl Display for MutationRates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "chance {:.2}, body count {:.1}, both {:.2}, body {:.2}, gradual step {:.3}",
+            self.chance, self.body_count, self.both_chance, self.body_chance, self.gradual_step
+        )
+    }
+}
+
+/// one change made to a genome when it was copied for a child, genes, neurons and connects are
+/// referred to by their index in the parent's genome at the time of the change
+#[derive(Debug, Clone, PartialEq)]
+pub enum MutationEvent {
+    AddGene {
+        index: usize,
+        /// None for Repeat and Up genes
+        kind: Option<NodeKind>,
+    },
+    DeleteGene {
+        index: usize,
+    },
+    EditGene {
+        index: usize,
+        field: &'static str,
+        old: f32,
+        new: f32,
+    },
+    EditGradual {
+        index: usize,
+        field: &'static str,
+        old: f32,
+        new: f32,
+    },
+    AddSynth {
+        amp: f32,
+        freq: f32,
+    },
+    AddConnect {
+        /// None for the bias
+        from: Option<usize>,
+        to: usize,
+        weight: f32,
+    },
+    AddHidden {
+        connect: usize,
+    },
+    AddMemory {
+        connect: usize,
+        time_constant: f32,
+        latch: bool,
+    },
+    TimeConstant {
+        neuron: usize,
+        old: f32,
+        new: f32,
+    },
+    ToggleLatch {
+        neuron: usize,
+        latch: bool,
+    },
+    DeleteConnect {
+        connect: usize,
+    },
+    DeleteNeuron {
+        neuron: usize,
+    },
+    EnableConnect {
+        connect: usize,
+        enabled: bool,
+    },
+    ShiftWeight {
+        connect: usize,
+        old: f32,
+        new: f32,
+    },
+    RandomWeight {
+        connect: usize,
+        old: f32,
+        new: f32,
+    },
+    Rate {
+        field: &'static str,
+        old: f32,
+        new: f32,
+    },
+}
+
+/// the value of one field of an event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventValue {
+    Index(usize),
+    Number(f32),
+    Flag(bool),
+    Name(&'static str),
+    Missing,
+}
+
+impl EventValue {
+    pub fn to_json(self) -> String {
+        match self {
+            EventValue::Index(index) => index.to_string(),
+            EventValue::Number(number) if number.is_finite() => number.to_string(),
+            EventValue::Flag(flag) => flag.to_string(),
+            // names are field and enum names, which never need escaping
+            EventValue::Name(name) => format!("\"{}\"", name),
+            EventValue::Number(_) | EventValue::Missing => "null".to_string(),
+        }
+    }
+}
+
+impl Display for EventValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventValue::Index(index) => write!(f, "{}", index),
+            EventValue::Number(number) => write!(f, "{:.3}", number),
+            EventValue::Flag(flag) => write!(f, "{}", flag),
+            EventValue::Name(name) => write!(f, "{}", name),
+            EventValue::Missing => write!(f, "none"),
+        }
+    }
+}
+
+impl MutationEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MutationEvent::AddGene { .. } => "AddGene",
+            MutationEvent::DeleteGene { .. } => "DeleteGene",
+            MutationEvent::EditGene { .. } => "EditGene",
+            MutationEvent::EditGradual { .. } => "EditGradual",
+            MutationEvent::AddSynth { .. } => "AddSynth",
+            MutationEvent::AddConnect { .. } => "AddConnect",
+            MutationEvent::AddHidden { .. } => "AddHidden",
+            MutationEvent::AddMemory { .. } => "AddMemory",
+            MutationEvent::TimeConstant { .. } => "TimeConstant",
+            MutationEvent::ToggleLatch { .. } => "ToggleLatch",
+            MutationEvent::DeleteConnect { .. } => "DeleteConnect",
+            MutationEvent::DeleteNeuron { .. } => "DeleteNeuron",
+            MutationEvent::EnableConnect { .. } => "EnableConnect",
+            MutationEvent::ShiftWeight { .. } => "ShiftWeight",
+            MutationEvent::RandomWeight { .. } => "RandomWeight",
+            MutationEvent::Rate { .. } => "Rate",
+        }
+    }
+    /// the event's fields by name
+    pub fn fields(&self) -> Vec<(&'static str, EventValue)> {
+        use EventValue::*;
+        let change = |old: f32, new: f32| [("old", Number(old)), ("new", Number(new))];
+        match *self {
+            MutationEvent::AddGene { index, kind } => vec![
+                ("index", Index(index)),
+                ("kind", kind.map_or(Missing, |kind| Name(kind.into()))),
+            ],
+            MutationEvent::DeleteGene { index } => vec![("index", Index(index))],
+            MutationEvent::EditGene {
+                index,
+                field,
+                old,
+                new,
+            }
+            | MutationEvent::EditGradual {
+                index,
+                field,
+                old,
+                new,
+            } => {
+                let mut fields = vec![("index", Index(index)), ("field", Name(field))];
+                fields.extend(change(old, new));
+                fields
+            }
+            MutationEvent::AddSynth { amp, freq } => {
+                vec![("amp", Number(amp)), ("freq", Number(freq))]
+            }
+            MutationEvent::AddConnect { from, to, weight } => vec![
+                ("from", from.map_or(Missing, Index)),
+                ("to", Index(to)),
+                ("weight", Number(weight)),
+            ],
+            MutationEvent::AddHidden { connect } | MutationEvent::DeleteConnect { connect } => {
+                vec![("connect", Index(connect))]
+            }
+            MutationEvent::AddMemory {
+                connect,
+                time_constant,
+                latch,
+            } => vec![
+                ("connect", Index(connect)),
+                ("time_constant", Number(time_constant)),
+                ("latch", Flag(latch)),
+            ],
+            MutationEvent::TimeConstant { neuron, old, new } => {
+                let mut fields = vec![("neuron", Index(neuron))];
+                fields.extend(change(old, new));
+                fields
+            }
+            MutationEvent::ToggleLatch { neuron, latch } => {
+                vec![("neuron", Index(neuron)), ("latch", Flag(latch))]
+            }
+            MutationEvent::DeleteNeuron { neuron } => vec![("neuron", Index(neuron))],
+            MutationEvent::EnableConnect { connect, enabled } => {
+                vec![("connect", Index(connect)), ("enabled", Flag(enabled))]
+            }
+            MutationEvent::ShiftWeight { connect, old, new }
+            | MutationEvent::RandomWeight { connect, old, new } => {
+                let mut fields = vec![("connect", Index(connect))];
+                fields.extend(change(old, new));
+                fields
+            }
+            MutationEvent::Rate { field, old, new } => {
+                let mut fields = vec![("field", Name(field))];
+                fields.extend(change(old, new));
+                fields
+            }
+        }
+    }
+    /// a JSON object with the event's name as "event" and its fields
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\"event\": \"{}\"", self.name());
+        for (name, value) in self.fields() {
+            json.push_str(&format!(", \"{}\": {}", name, value.to_json()));
+        }
+        json.push('}');
+        json
+    }
+}
+
+impl Display for MutationEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())?;
+        for (name, value) in self.fields() {
+            write!(f, " {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use int_enum::IntEnum;
 use nannou::prelude::*;
-use strum_macros::{EnumCount, EnumIter};
+use strum::EnumCount;
+use strum_macros::{EnumCount, EnumIter, IntoStaticStr};
 
 use crate::model::world::{chunks::TIDE_MULT, math::sense_angle_diff};
 
@@ -9,7 +10,8 @@ use nannou::glam::Vec2;
 use super::{
     chunks::Chunk,
     collection::GenId,
-    math::{is_zero_vec2, Angle},
+    math::{is_zero_vec2, saturate, Angle},
+    tag::Tag,
 };
 
 pub const LEAF_ENERGY_RATE: f32 = 0.000_16;
@@ -17,9 +19,22 @@ pub const ENERGY_LOSS_RATE: f32 = 0.000_002;
 pub const MUSCLE_ENERGY_RATE: f32 = ENERGY_LOSS_RATE * 0.25;
 pub const JET_SPEED: f32 = 1.0;
 pub const JET_ENERGY_RATE: f32 = ENERGY_LOSS_RATE * 0.25;
+pub const JET_STEER_ANGLE: f32 = PI / 4.0;
+pub const STORAGE_RELEASE_MAX: f32 = 4.0;
+pub const LAY_THRESHOLD: f32 = 0.5;
+pub const LAY_MAX_INVESTMENT: f32 = 4.0;
+pub const EYE_RANGE_MULT: f32 = 10.0;
+pub const MAX_LIFESPAN: u32 = 32_768;
+pub const DEAD_COLOR: (f32, f32, f32) = (0.5, 0.5, 0.5);
+/// how much faster metabolism gets per unit of extra temperature
+pub const TEMPERATURE_METABOLISM: f32 = 1.0;
+/// how much faster metabolism gets per unit of temperature outside the tolerated range
+pub const TEMPERATURE_STRESS: f32 = 8.0;
+/// how much faster metabolism gets per unit of tolerance
+pub const TOLERANCE_COST: f32 = 2.0;
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, Eq, PartialEq, IntEnum, EnumIter, EnumCount)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, IntEnum, EnumIter, EnumCount, IntoStaticStr)]
 pub enum NodeKind {
     Egg = 0,
     Leaf = 1,
@@ -31,8 +46,27 @@ pub enum NodeKind {
 }
 
 impl NodeKind {
-    pub fn is_output(&self) -> bool {
-        matches!(self, NodeKind::Jet)
+    /// color of the node when it has no energy, also what eyes see
+    pub fn color(&self) -> (f32, f32, f32) {
+        match self {
+            NodeKind::Leaf => (0.5, 0.7, 0.5),
+            NodeKind::Storage => (0.7, 0.5, 0.5),
+            NodeKind::Mouth => (0.7, 0.5, 0.7),
+            NodeKind::Egg => (0.5, 0.5, 0.7),
+            NodeKind::Spike => (0.7, 0.7, 0.5),
+            NodeKind::Shell => (0.5, 0.7, 0.7),
+            NodeKind::Jet => (0.7, 0.6, 0.5),
+        }
+    }
+    /// the output channels the brain can drive for this kind, muscles are added separately
+    pub fn outputs(&self) -> &'static [OutputKind] {
+        match self {
+            NodeKind::Jet => &[OutputKind::Thrust, OutputKind::Steer],
+            NodeKind::Mouth => &[OutputKind::Open],
+            NodeKind::Storage => &[OutputKind::Release],
+            NodeKind::Egg => &[OutputKind::Lay],
+            _ => &[],
+        }
     }
     pub fn is_input(&self) -> bool {
         false
@@ -55,6 +89,91 @@ pub enum SenseKind {
     CollideSpeed = 8,
 
     Eye = 9,
+
+    CollideKin = 10,
+
+    Damage = 11,
+    LostNodes = 12,
+
+    AgeFraction = 13,
+    Depth = 14,
+    OrganismSize = 15,
+    OrganismEnergy = 16,
+    DayPhase = 17,
+    Temperature = 18,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, IntEnum, EnumIter, EnumCount)]
+pub enum OutputKind {
+    /// how far the muscle joined at this node contracts
+    Muscle = 0,
+    /// how hard a jet pushes
+    Thrust = 1,
+    /// how far a jet turns away from its bone
+    Steer = 2,
+    /// a mouth only eats when this is not negative
+    Open = 3,
+    /// how much a storage node gives its energy away to its neighbors
+    Release = 4,
+    /// an egg lays when this is above LAY_THRESHOLD, and gives the child more energy the higher it is
+    Lay = 5,
+}
+
+impl OutputKind {
+    /// the activate a node starts with, and keeps if the brain never drives this output
+    pub fn default_activate(&self) -> f32 {
+        match self {
+            // lay with the gene's starting energy, like when the brain can't control eggs
+            OutputKind::Lay => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, IntEnum, EnumIter, EnumCount)]
+pub enum RayChannel {
+    /// 1 when the seen node is touching the eye, 0 when nothing is seen
+    Distance = 0,
+    Red = 1,
+    Green = 2,
+    Blue = 3,
+    /// how related the seen node's organism is, 0 if nothing alive is seen
+    Kin = 4,
+}
+
+/// where an input neuron reads its value from on a node
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InputKind {
+    Sense,
+    Ray(u8, RayChannel),
+}
+
+/// a compound eye, made of rays spread evenly over the field of view
+#[derive(Debug, Clone)]
+pub struct Eye {
+    pub rays: u8,
+    pub fov: f32,
+    /// what each ray saw, indexed by RayChannel
+    pub seen: Vec<[f32; RayChannel::COUNT]>,
+}
+
+impl Eye {
+    pub fn new(rays: u8, fov: f32) -> Eye {
+        Eye {
+            rays,
+            fov,
+            seen: vec![[0.; RayChannel::COUNT]; rays as usize],
+        }
+    }
+    /// angle of the ray relative to the direction the eye faces
+    pub fn ray_offset(&self, ray: u8) -> f32 {
+        if self.rays <= 1 {
+            return 0.;
+        }
+        self.fov * (ray as f32 / (self.rays - 1) as f32 - 0.5)
+    }
 }
 
 /// SenseCalculate determines whether you calculate the sense or skip it, based on if the brain actually has outputs. It is an optimization and should not affect the simulation.
@@ -65,6 +184,7 @@ pub enum SenseCalculate {
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum LifeState {
     Alive {
         kind: NodeKind,
@@ -73,9 +193,22 @@ pub enum LifeState {
         gene_index: Option<usize>,
         energy_weight: f32,
         lifespan: u32,
+        /// how many bones away from the organism's first node
+        depth: u32,
+        /// how far the node reaches up, from the length of its bone, higher leaves get more light
+        height: f32,
+        /// the temperature the node is best at
+        optimal_temperature: f32,
+        /// how far from the optimal temperature the node can go without stress
+        temperature_tolerance: f32,
 
         sense: Option<(SenseKind, SenseCalculate)>,
-        activate: f32,
+        eye: Option<Eye>,
+        activate: [f32; OutputKind::COUNT],
+        /// tag of the organism this node belongs to
+        tag: Tag,
+        /// the organism this node belongs to, None until the organism is added to the world
+        organism: Option<GenId>,
     },
     Dead {
         decay: u32,
@@ -94,6 +227,8 @@ pub struct Node {
 
     pub delete: bool,
     pub splat: bool,
+    /// set when the node was eaten or spiked this tick
+    pub wounded: bool,
 }
 impl Node {
     #[allow(clippy::too_many_arguments)]
@@ -106,7 +241,12 @@ impl Node {
         gene_index: Option<usize>,
         parent: Option<(GenId, Angle)>,
         lifespan: u32,
+        depth: u32,
+        height: f32,
+        (optimal_temperature, temperature_tolerance): (f32, f32),
         sense_kind: Option<(SenseKind, bool)>,
+        eye: Option<Eye>,
+        tag: Tag,
     ) -> Node {
         Node {
             pos,
@@ -121,6 +261,10 @@ impl Node {
                 gene_index,
                 energy_weight,
                 lifespan,
+                depth,
+                height,
+                optimal_temperature,
+                temperature_tolerance,
 
                 sense: sense_kind.map(|(sense, should_calculate)| {
                     (
@@ -132,13 +276,19 @@ impl Node {
                         },
                     )
                 }),
-                activate: 0.,
+                eye,
+                activate: std::array::from_fn(|i| {
+                    OutputKind::from_int(i as u8).unwrap().default_activate()
+                }),
+                tag,
+                organism: None,
             },
 
             energy,
 
             delete: false,
             splat: false,
+            wounded: false,
         }
     }
     pub fn new_dead(pos: Point2, radius: f32, energy: f32) -> Node {
@@ -154,6 +304,7 @@ impl Node {
 
             delete: false,
             splat: false,
+            wounded: false,
         }
     }
     pub fn accel(&mut self, accel: Vec2) {
@@ -190,29 +341,50 @@ impl Node {
             LifeState::Alive {
                 ref mut age,
                 lifespan,
+                depth,
                 kind,
                 ref mut sense,
                 parent,
                 activate,
+                optimal_temperature,
+                temperature_tolerance,
+                height,
                 ..
             } => {
-                let (max_energy, energy_change) = get_energy_change(self.radius, *kind, chunk);
+                let (max_energy, energy_change) = get_energy_change(
+                    self.radius,
+                    *kind,
+                    chunk,
+                    *height,
+                    *optimal_temperature,
+                    *temperature_tolerance,
+                );
                 if let Some((kind, SenseCalculate::Calculate(ref mut sense))) = sense {
                     use SenseKind::*;
                     *sense = match kind {
                         Sun => chunk.sun,
                         Energy => self.energy / max_energy,
-                        Age => *age as f32,
+                        Age => *age as f32 / MAX_LIFESPAN as f32,
+                        AgeFraction => *age as f32 / *lifespan as f32,
+                        Depth => saturate(*depth as f32, 4.),
+                        Temperature => chunk.temperature,
                         TideSpeed => (chunk.tide / TIDE_MULT).length() / 2.0.sqrt(),
                         TideAngle => parent
                             .map(|(_, a)| sense_angle_diff(a, Angle::from_vec2(chunk.tide)))
                             .unwrap_or(0.),
                         // handled by collider
-                        CollideAngle | CollideKind | CollideRadius | CollideSpeed | Eye => 0.,
+                        CollideAngle | CollideKind | CollideRadius | CollideSpeed | CollideKin
+                        | Eye | Damage => 0.,
+                        // handled by organism
+                        LostNodes | OrganismSize | OrganismEnergy | DayPhase => 0.,
                     }
                 }
                 if let (NodeKind::Jet, Some((_, angle))) = (kind, parent) {
-                    let jet_mult = JET_SPEED * activate.clamp(0.0, 5.0);
+                    let jet_mult =
+                        JET_SPEED * activate[OutputKind::Thrust as usize].clamp(0.0, 5.0);
+                    let steer =
+                        activate[OutputKind::Steer as usize].clamp(-1.0, 1.0) * JET_STEER_ANGLE;
+                    let angle = Angle(angle.0 + steer);
                     self.accel += angle.to_vec2().normalize_or_zero() * jet_mult * -1.;
                     self.energy -= JET_ENERGY_RATE * jet_mult;
                 }
@@ -244,6 +416,33 @@ impl Node {
         }
     }
 
+    /// the height of a living leaf, None for other nodes
+    pub fn leaf_height(&self) -> Option<f32> {
+        match self.life_state {
+            LifeState::Alive {
+                kind: NodeKind::Leaf,
+                height,
+                ..
+            } => Some(height),
+            _ => None,
+        }
+    }
+    /// the organism that owns this node, None if the node is dead
+    pub fn owner(&self) -> Option<GenId> {
+        match self.life_state {
+            LifeState::Alive { organism, .. } => organism,
+            LifeState::Dead { .. } => None,
+        }
+    }
+    pub fn set_owner(&mut self, owner: GenId) {
+        if let LifeState::Alive {
+            ref mut organism, ..
+        } = self.life_state
+        {
+            *organism = Some(owner);
+        }
+    }
+
     pub fn unwrap_gene_index_mut(&mut self) -> &mut Option<usize> {
         match self.life_state {
             LifeState::Alive {
@@ -258,6 +457,21 @@ impl Node {
             LifeState::Dead { .. } => panic!("dead node has no gene index"),
         }
     }
+    pub fn unwrap_depth(&self) -> u32 {
+        match self.life_state {
+            LifeState::Alive { depth, .. } => depth,
+            LifeState::Dead { .. } => panic!("dead node has no depth"),
+        }
+    }
+    pub fn sense_kind(&self) -> Option<SenseKind> {
+        match self.life_state {
+            LifeState::Alive {
+                sense: Some((kind, _)),
+                ..
+            } => Some(kind),
+            _ => None,
+        }
+    }
     pub fn unwrap_kind(&self) -> &NodeKind {
         match self.life_state {
             LifeState::Alive { ref kind, .. } => kind,
@@ -268,12 +482,59 @@ impl Node {
         self.radius.powi(3) / 50.0
     }
 
-    pub fn unwrap_activate(&self) -> &f32 {
+    pub fn unwrap_activate(&self, output: OutputKind) -> f32 {
         match self.life_state {
-            LifeState::Alive { ref activate, .. } => activate,
+            LifeState::Alive { ref activate, .. } => activate[output as usize],
             LifeState::Dead { .. } => panic!("dead node has no activate"),
         }
     }
+    /// energy weight used to balance energy across bones, storage can lower it to release energy
+    pub fn energy_weight(&self) -> Option<f32> {
+        match self.life_state {
+            LifeState::Alive {
+                energy_weight,
+                kind: NodeKind::Storage,
+                ref activate,
+                ..
+            } => Some(
+                energy_weight
+                    / (1. + activate[OutputKind::Release as usize].clamp(0., STORAGE_RELEASE_MAX)),
+            ),
+            LifeState::Alive { energy_weight, .. } => Some(energy_weight),
+            LifeState::Dead { .. } => None,
+        }
+    }
+    /// color of the node when it has no energy
+    pub fn color(&self) -> (f32, f32, f32) {
+        match self.life_state {
+            LifeState::Alive { kind, .. } => kind.color(),
+            LifeState::Dead { .. } => DEAD_COLOR,
+        }
+    }
+    /// how related this node's organism is to the tag, 0 if the node is dead
+    pub fn tag_similarity(&self, tag: &Tag) -> f32 {
+        match &self.life_state {
+            LifeState::Alive { tag: own_tag, .. } => own_tag.similarity(tag),
+            LifeState::Dead { .. } => 0.,
+        }
+    }
+    /// value an input neuron reads, None if it isn't calculated
+    pub fn input(&self, input: InputKind) -> Option<f32> {
+        match input {
+            InputKind::Sense => self.sense(),
+            InputKind::Ray(ray, channel) => match &self.life_state {
+                LifeState::Alive {
+                    sense: Some((_, SenseCalculate::Calculate(_))),
+                    eye: Some(eye),
+                    ..
+                } => eye
+                    .seen
+                    .get(ray as usize)
+                    .map(|seen| seen[channel as usize]),
+                _ => None,
+            },
+        }
+    }
     pub fn sense(&self) -> Option<f32> {
         match &self.life_state {
             LifeState::Alive { sense, .. } => sense.as_ref().and_then(|(_, sense)| match sense {
@@ -283,24 +544,49 @@ impl Node {
             LifeState::Dead { .. } => None,
         }
     }
-    pub fn activate(&mut self, new_activate: f32) {
+    /// sets the sense if the node has this kind of sense and calculates it
+    pub fn set_sense(&mut self, sense_kind: SenseKind, new_sense: f32) {
+        if let LifeState::Alive {
+            sense: Some((kind, SenseCalculate::Calculate(ref mut sense))),
+            ..
+        } = self.life_state
+        {
+            if kind == sense_kind {
+                *sense = new_sense;
+            }
+        }
+    }
+    pub fn activate(&mut self, output: OutputKind, new_activate: f32) {
         match self.life_state {
             LifeState::Alive {
                 ref mut activate, ..
-            } => *activate = new_activate,
+            } => activate[output as usize] = new_activate,
             LifeState::Dead { .. } => {}
         }
     }
 }
 
-fn get_energy_change(radius: f32, kind: NodeKind, chunk: &Chunk) -> (f32, f32) {
+fn get_energy_change(
+    radius: f32,
+    kind: NodeKind,
+    chunk: &Chunk,
+    height: f32,
+    optimal_temperature: f32,
+    temperature_tolerance: f32,
+) -> (f32, f32) {
     let base_max_energy = radius.powi(3) / 50.0 * 16.;
-    let base_energy_change = -ENERGY_LOSS_RATE * radius.powi(3);
+    // warmer water burns more, as does being outside the tolerated range or tolerating a lot
+    let stress = ((chunk.temperature - optimal_temperature).abs() - temperature_tolerance).max(0.);
+    let metabolism = (1. + TEMPERATURE_METABOLISM * (chunk.temperature - 0.5)).max(0.1)
+        * (1. + TEMPERATURE_STRESS * stress)
+        * (1. + TOLERANCE_COST * temperature_tolerance);
+    let base_energy_change = -ENERGY_LOSS_RATE * radius.powi(3) * metabolism;
     match kind {
         NodeKind::Storage => (base_max_energy * 4., base_energy_change * 0.25),
         NodeKind::Leaf => (
             base_max_energy,
-            base_energy_change + LEAF_ENERGY_RATE * radius.powi(2) * chunk.sun,
+            base_energy_change
+                + LEAF_ENERGY_RATE * radius.powi(2) * chunk.sun * chunk.light_share(height),
         ),
         _ => (base_max_energy, base_energy_change),
     }
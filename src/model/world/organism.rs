@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use nannou::prelude::*;
 
 use super::bone::Bone;
+use super::chunks::SunCycle;
 use super::brain::Brain;
 use super::collection::GenId;
 use super::collection::{Collection, CollectionView};
@@ -10,10 +11,12 @@ use super::collide::Collider;
 use super::gene::BuildId;
 use super::gene::Gene;
 use super::genome::Genome;
-use super::math::Angle;
+use super::math::{saturate, Angle};
 use super::muscle::Muscle;
-use super::node::NodeKind;
-use super::node::{LifeState, Node};
+use super::node::{LifeState, Node, SenseKind};
+use super::node::{NodeKind, OutputKind, LAY_MAX_INVESTMENT, LAY_THRESHOLD};
+use super::mutation::MutationEvent;
+use super::phylogeny::LineageId;
 
 const MAX_NODE_CHILDREN: usize = 4;
 fn get_node_children(
@@ -61,7 +64,18 @@ pub struct Organism {
     node_ids: Vec<GenId>,
     pub new_organisms: Vec<Organism>,
     pub delete: bool,
-    next_child_genome: Option<Genome>,
+    /// the genome and the mutations that made it
+    next_child_genome: Option<(Genome, Vec<MutationEvent>)>,
+    /// how many nodes were eaten this tick
+    pub lost_nodes: u32,
+    /// set when the organism is added to the phylogeny
+    pub lineage: LineageId,
+    pub parent_lineage: Option<LineageId>,
+    pub birth_tick: u64,
+    /// mutations since the parent
+    pub mutations: Vec<MutationEvent>,
+    /// how many children it has laid
+    pub offspring: u32,
 }
 
 impl Organism {
@@ -70,8 +84,15 @@ impl Organism {
         let Gene::Build((gene, _)) = gene else { unreachable!() };
         let index = genome.body.get_next_deeper(index);
         let brain = Brain::from_plan(&genome.brain);
-        let node_id =
-            nodes.push(gene.build_node(pos, index, energy, None, brain.does_calculate_neurons()));
+        let node_id = nodes.push(gene.build_node(
+            pos,
+            index,
+            energy,
+            None,
+            0,
+            brain.does_calculate_neurons(),
+            &genome.tag,
+        ));
         Organism {
             genome,
             brain,
@@ -81,10 +102,26 @@ impl Organism {
             new_organisms: Vec::new(),
             delete: false,
             next_child_genome: None,
+            lost_nodes: 0,
+            lineage: 0,
+            parent_lineage: None,
+            birth_tick: 0,
+            mutations: Vec::new(),
+            offspring: 0,
+        }
+    }
+
+    /// tells the organism's nodes who owns them, must be called once the organism has an id
+    pub fn claim_nodes(&self, id: GenId, nodes: &mut Collection<Node>) {
+        for node_id in &self.node_ids {
+            if let Some(node) = nodes.get_mut(*node_id) {
+                node.set_owner(id);
+            }
         }
     }
 
     pub fn clear_dead(&mut self, nodes: &Collection<Node>) {
+        // dead nodes lose their owner along with the rest of their life state
         self.node_ids.retain(|id| match nodes.get(*id) {
             Some(node) => node.is_alive(),
             None => false,
@@ -94,7 +131,8 @@ impl Organism {
             self.delete = true;
         }
     }
-    pub fn think(&mut self, nodes: &mut CollectionView<Node>, tick: u64) {
+    pub fn think(&mut self, nodes: &mut CollectionView<Node>, tick: u64, sun_cycle: &SunCycle) {
+        self.sense(nodes, tick, sun_cycle);
         let think_energy = self
             .brain
             .step(&self.genome.brain, &self.build_id_map, nodes, tick);
@@ -105,6 +143,33 @@ impl Organism {
             });
         }
     }
+    /// sets the senses that depend on the whole organism
+    fn sense(&self, nodes: &mut CollectionView<Node>, tick: u64, sun_cycle: &SunCycle) {
+        let (energy, max_energy) = self
+            .node_ids
+            .iter()
+            .filter_map(|node_id| nodes.get(*node_id))
+            .fold((0., 0.), |(energy, max_energy), node| {
+                (energy + node.energy, max_energy + node.max_energy())
+            });
+        let lost_nodes = saturate(self.lost_nodes as f32, 1.);
+        let size = saturate(self.node_ids.len() as f32, 16.);
+        let energy = energy / max_energy;
+        let time_of_day = sun_cycle.time_of_day(tick);
+        for node_id in &self.node_ids {
+            // safe because no 2 organisms share nodes
+            let Some(node) = nodes.get_mut(*node_id) else {continue};
+            let Some(kind) = node.sense_kind() else {continue};
+            let value = match kind {
+                SenseKind::LostNodes => lost_nodes,
+                SenseKind::OrganismSize => size,
+                SenseKind::OrganismEnergy => energy,
+                SenseKind::DayPhase => time_of_day,
+                _ => continue,
+            };
+            node.set_sense(kind, value);
+        }
+    }
     pub fn grow(
         &mut self,
         nodes: &mut Collection<Node>,
@@ -156,8 +221,13 @@ impl Organism {
             child_gene_index,
             gene.starting_energy,
             Some((node_id, nodes[node_id].pos())),
+            nodes[node_id].unwrap_depth() + 1,
             self.brain.does_calculate_neurons(),
+            &self.genome.tag,
         ));
+        if let Some(owner) = nodes[node_id].owner() {
+            nodes[child_id].set_owner(owner);
+        }
         self.node_ids.push(child_id);
         self.build_id_map.insert(build_id.clone(), child_id);
 
@@ -189,6 +259,12 @@ impl Organism {
         let Some(Node { life_state: LifeState::Alive { .. }, .. }) = nodes.get(node_id) else { return };
         // make sure node is an egg
         let NodeKind::Egg = nodes[node_id].unwrap_kind() else { return };
+        // make sure the brain wants to lay
+        let lay = nodes[node_id].unwrap_activate(OutputKind::Lay);
+        if lay < LAY_THRESHOLD {
+            return;
+        }
+        let investment = lay.min(LAY_MAX_INVESTMENT);
 
         let children = get_node_children(nodes, node_id, &self.node_ids);
         let spawn_direction = get_spawn_direction(nodes, nodes[node_id].pos(), &children);
@@ -196,15 +272,19 @@ impl Organism {
         // save genome for child, so that high energy cost genes aren't deleted
         self.next_child_genome.get_or_insert_with(|| {
             let mut new_genome = self.genome.clone();
-            if random::<f32>() < 0.5 {
-                new_genome.mutate();
+            let mut mutations = Vec::new();
+            if random::<f32>() < self.genome.rates.chance {
+                mutations = new_genome.mutate();
             }
-            new_genome
+            (new_genome, mutations)
         });
-        let new_genome = self.next_child_genome.take().unwrap();
+        let (new_genome, mutations) = self.next_child_genome.take().unwrap();
 
         let Gene::Build((build_gene, _)) = new_genome.body.get_start_gene().1 else {unreachable!()};
-        let energy_cost = build_gene.energy_cost() + new_genome.body.len() as f32 / 10.;
+        let starting_energy = build_gene.starting_energy * investment;
+        let energy_cost = build_gene.energy_cost() - build_gene.starting_energy
+            + starting_energy
+            + new_genome.body.len() as f32 / 10.;
 
         let min_child_distance = nodes[node_id].radius + build_gene.node_radius;
         let child_start_pos =
@@ -227,9 +307,22 @@ impl Organism {
         }
         nodes[node_id].energy -= energy_cost;
 
-        let starting_energy = build_gene.starting_energy;
-        let organism = Organism::new(child_start_pos, new_genome, starting_energy, nodes);
+        let mut organism = Organism::new(child_start_pos, new_genome, starting_energy, nodes);
+        organism.parent_lineage = Some(self.lineage);
+        organism.mutations = mutations;
         self.new_organisms.push(organism);
+        self.offspring += 1;
+    }
+    /// the genes the organism's nodes will build next
+    pub fn growing_genes(&self, nodes: &Collection<Node>) -> Vec<usize> {
+        self.node_ids
+            .iter()
+            .filter_map(|node_id| nodes.get(*node_id))
+            .filter(|node| node.is_alive())
+            .filter_map(|node| *node.unwrap_gene_index())
+            .filter_map(|index| self.genome.body.get_build(index))
+            .map(|(build_index, _)| build_index)
+            .collect()
     }
     pub fn node_ids(&self) -> &[GenId] {
         &self.node_ids
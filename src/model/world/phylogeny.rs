@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::hash::Hasher;
+
+use super::genome::Genome;
+use super::mutation::MutationEvent;
+use super::organism::Organism;
+
+pub type LineageId = u64;
+
+/// a lineage merged from a long chain only keeps this many of its latest mutations
+const MAX_LINEAGE_MUTATIONS: usize = 64;
+
+/// one organism in the tree, or a chain of them once the ones in between are collapsed
+#[derive(Debug, Clone)]
+pub struct Lineage {
+    pub id: LineageId,
+    pub parent: Option<LineageId>,
+    pub birth: u64,
+    /// None while alive
+    pub death: Option<u64>,
+    pub genome_hash: u64,
+    /// the latest mutations since the parent
+    pub mutations: Vec<MutationEvent>,
+    /// how many earlier mutations were dropped to keep the list short
+    pub earlier_mutations: u32,
+    children: Vec<LineageId>,
+}
+
+/// the family tree of every organism with living descendants
+///
+/// dead leaves are removed, and dead organisms with only one child are merged into the child,
+/// so the tree only grows with the number of living organisms
+#[derive(Debug, Clone, Default)]
+pub struct Phylogeny {
+    lineages: HashMap<LineageId, Lineage>,
+    next_id: LineageId,
+}
+
+/// 64 bit FNV-1a, which unlike the standard hasher gives the same hash on every build
+struct FnvHasher(u64);
+
+impl FnvHasher {
+    fn new() -> FnvHasher {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    // fixed byte order so the hash doesn't depend on the machine either
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+}
+
+fn hash_genome(genome: &Genome) -> u64 {
+    let mut hasher = FnvHasher::new();
+    genome.hash_genes(&mut hasher);
+    hasher.finish()
+}
+
+impl Phylogeny {
+    pub fn new() -> Phylogeny {
+        Phylogeny::default()
+    }
+    pub fn len(&self) -> usize {
+        self.lineages.len()
+    }
+    /// gives the organism a new lineage id, under its parent's lineage
+    pub fn record_birth(&mut self, organism: &mut Organism, tick: u64) {
+        organism.lineage = self.add(
+            organism.parent_lineage,
+            tick,
+            hash_genome(&organism.genome),
+            organism.mutations.clone(),
+        );
+        organism.birth_tick = tick;
+    }
+    /// adds a living lineage, under the parent if it's still in the tree
+    fn add(
+        &mut self,
+        parent: Option<LineageId>,
+        tick: u64,
+        genome_hash: u64,
+        mutations: Vec<MutationEvent>,
+    ) -> LineageId {
+        let id = self.next_id;
+        self.next_id += 1;
+        let parent = parent.filter(|parent| self.lineages.contains_key(parent));
+        if let Some(parent) = parent {
+            self.lineages.get_mut(&parent).unwrap().children.push(id);
+        }
+        self.lineages.insert(
+            id,
+            Lineage {
+                id,
+                parent,
+                birth: tick,
+                death: None,
+                genome_hash,
+                mutations,
+                earlier_mutations: 0,
+                children: Vec::new(),
+            },
+        );
+        id
+    }
+    pub fn record_death(&mut self, id: LineageId, tick: u64) {
+        let Some(lineage) = self.lineages.get_mut(&id) else { return };
+        lineage.death = Some(tick);
+        self.prune(id);
+    }
+    /// removes the lineage if it's a dead leaf, or merges it into its only child,
+    /// then does the same to its parent
+    fn prune(&mut self, mut id: LineageId) {
+        // a loop rather than recursion, a long dead chain can be as deep as the tree
+        loop {
+            let Some(lineage) = self.lineages.get(&id) else { return };
+            if lineage.death.is_none() || lineage.children.len() > 1 {
+                return;
+            }
+            let lineage = self.lineages.remove(&id).unwrap();
+            if let Some(&child_id) = lineage.children.first() {
+                let child = self.lineages.get_mut(&child_id).unwrap();
+                child.parent = lineage.parent;
+                // the merged lineage's mutations happened first
+                let mut mutations = lineage.mutations;
+                mutations.append(&mut child.mutations);
+                let dropped = mutations.len().saturating_sub(MAX_LINEAGE_MUTATIONS);
+                mutations.drain(..dropped);
+                child.mutations = mutations;
+                child.earlier_mutations += lineage.earlier_mutations + dropped as u32;
+                let parent = lineage
+                    .parent
+                    .and_then(|parent| self.lineages.get_mut(&parent));
+                if let Some(parent) = parent {
+                    for sibling in parent.children.iter_mut() {
+                        if *sibling == id {
+                            *sibling = child_id;
+                        }
+                    }
+                }
+                return;
+            }
+            let Some(parent_id) = lineage.parent else { return };
+            let Some(parent) = self.lineages.get_mut(&parent_id) else { return };
+            parent.children.retain(|child| *child != id);
+            id = parent_id;
+        }
+    }
+    fn roots(&self) -> Vec<LineageId> {
+        let mut roots: Vec<_> = self
+            .lineages
+            .values()
+            .filter(|lineage| lineage.parent.is_none())
+            .map(|lineage| lineage.id)
+            .collect();
+        roots.sort_unstable();
+        roots
+    }
+    fn write_newick(&self, out: &mut String, root: LineageId) {
+        // each lineage being written and how many of its children are done, a stack rather
+        // than recursion since a chain of single births can be as deep as there are organisms
+        let mut stack = vec![(root, 0)];
+        while let Some(&(id, written)) = stack.last() {
+            let lineage = &self.lineages[&id];
+            if let Some(&child) = lineage.children.get(written) {
+                out.push(if written == 0 { '(' } else { ',' });
+                stack.last_mut().unwrap().1 += 1;
+                stack.push((child, 0));
+                continue;
+            }
+            stack.pop();
+            if !lineage.children.is_empty() {
+                out.push(')');
+            }
+            // branches are as long as the time between the parent's birth and this one's
+            let parent_birth = lineage
+                .parent
+                .and_then(|parent| self.lineages.get(&parent))
+                .map_or(lineage.birth, |parent| parent.birth);
+            write!(out, "L{}:{}", id, lineage.birth - parent_birth).unwrap();
+        }
+    }
+    /// the tree in Newick format, with one root holding every original organism
+    pub fn to_newick(&self) -> String {
+        let mut out = String::from("(");
+        for (i, root) in self.roots().into_iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            self.write_newick(&mut out, root);
+        }
+        out.push_str(");");
+        out
+    }
+    /// every lineage as a JSON array of objects
+    pub fn to_json(&self) -> String {
+        let mut lineages: Vec<_> = self.lineages.values().collect();
+        lineages.sort_by_key(|lineage| lineage.id);
+        let mut out = String::from("[\n");
+        for (i, lineage) in lineages.iter().enumerate() {
+            let optional = |value: Option<u64>| value.map_or("null".to_string(), |v| v.to_string());
+            write!(
+                out,
+                "  {{\"id\": {}, \"parent\": {}, \"birth\": {}, \"death\": {}, \"genome_hash\": \"{:016x}\", \"mutations\": [{}], \"earlier_mutations\": {}}}",
+                lineage.id,
+                optional(lineage.parent),
+                lineage.birth,
+                optional(lineage.death),
+                lineage.genome_hash,
+                lineage
+                    .mutations
+                    .iter()
+                    .map(|event| event.to_json())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                lineage.earlier_mutations,
+            )
+            .unwrap();
+            out.push_str(if i + 1 < lineages.len() { ",\n" } else { "\n" });
+        }
+        out.push(']');
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deletes(count: usize) -> Vec<MutationEvent> {
+        (0..count)
+            .map(|index| MutationEvent::DeleteGene { index })
+            .collect()
+    }
+
+    #[test]
+    fn dead_leaf_is_removed() {
+        let mut phylogeny = Phylogeny::new();
+        let root = phylogeny.add(None, 0, 0, Vec::new());
+        let leaf = phylogeny.add(Some(root), 10, 0, Vec::new());
+        phylogeny.record_death(leaf, 20);
+        assert_eq!(phylogeny.len(), 1);
+        assert!(phylogeny.lineages[&root].children.is_empty());
+        assert_eq!(phylogeny.to_newick(), "(L0:0);");
+    }
+
+    #[test]
+    fn dead_parents_of_a_dead_leaf_are_removed() {
+        let mut phylogeny = Phylogeny::new();
+        let root = phylogeny.add(None, 0, 0, Vec::new());
+        let middle = phylogeny.add(Some(root), 10, 0, Vec::new());
+        let leaf = phylogeny.add(Some(middle), 20, 0, Vec::new());
+        phylogeny.record_death(middle, 30);
+        phylogeny.record_death(leaf, 40);
+        assert_eq!(phylogeny.len(), 1);
+        assert_eq!(phylogeny.to_newick(), "(L0:0);");
+    }
+
+    #[test]
+    fn dead_single_child_lineage_is_merged() {
+        let mut phylogeny = Phylogeny::new();
+        let root = phylogeny.add(None, 0, 0, Vec::new());
+        let middle = phylogeny.add(Some(root), 10, 0, deletes(1));
+        let leaf = phylogeny.add(Some(middle), 25, 0, deletes(2));
+        phylogeny.record_death(middle, 30);
+        assert_eq!(phylogeny.len(), 2);
+        assert_eq!(phylogeny.lineages[&root].children, vec![leaf]);
+        let merged = &phylogeny.lineages[&leaf];
+        assert_eq!(merged.parent, Some(root));
+        // the middle lineage's mutation comes first
+        assert_eq!(
+            merged.mutations,
+            vec![
+                MutationEvent::DeleteGene { index: 0 },
+                MutationEvent::DeleteGene { index: 0 },
+                MutationEvent::DeleteGene { index: 1 },
+            ]
+        );
+        // the branch spans both births
+        assert_eq!(phylogeny.to_newick(), "((L2:25)L0:0);");
+    }
+
+    #[test]
+    fn merged_mutations_are_capped() {
+        let mut phylogeny = Phylogeny::new();
+        let middle = phylogeny.add(None, 0, 0, deletes(40));
+        let leaf = phylogeny.add(Some(middle), 10, 0, deletes(40));
+        phylogeny.record_death(middle, 20);
+        let merged = &phylogeny.lineages[&leaf];
+        assert_eq!(merged.mutations.len(), MAX_LINEAGE_MUTATIONS);
+        assert_eq!(merged.earlier_mutations, 16);
+        // the oldest are the ones dropped
+        assert_eq!(merged.mutations[0], MutationEvent::DeleteGene { index: 16 });
+        assert_eq!(merged.parent, None);
+    }
+
+    #[test]
+    fn newick_after_removing_a_leaf_and_merging() {
+        let mut phylogeny = Phylogeny::new();
+        let root = phylogeny.add(None, 0, 0, Vec::new());
+        let other = phylogeny.add(Some(root), 5, 0, Vec::new());
+        let middle = phylogeny.add(Some(root), 10, 0, Vec::new());
+        let kept = phylogeny.add(Some(middle), 20, 0, Vec::new());
+        let lost = phylogeny.add(Some(middle), 30, 0, Vec::new());
+        assert_eq!(phylogeny.to_newick(), "((L1:5,(L3:10,L4:20)L2:10)L0:0);");
+        phylogeny.record_death(middle, 40);
+        // still has two children so it stays
+        assert_eq!(phylogeny.len(), 5);
+        phylogeny.record_death(lost, 50);
+        // the leaf goes, leaving the middle with one child to merge into
+        assert_eq!(phylogeny.len(), 3);
+        assert_eq!(phylogeny.lineages[&root].children, vec![other, kept]);
+        assert_eq!(phylogeny.to_newick(), "((L1:5,L3:20)L0:0);");
+    }
+
+    #[test]
+    fn deep_chain_writes_without_overflowing() {
+        let mut phylogeny = Phylogeny::new();
+        let mut parent = None;
+        for tick in 0..100_000 {
+            parent = Some(phylogeny.add(parent, tick, 0, Vec::new()));
+        }
+        let newick = phylogeny.to_newick();
+        assert!(newick.starts_with("(((("));
+        assert!(newick.ends_with("L0:0);"));
+    }
+}
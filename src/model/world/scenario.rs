@@ -0,0 +1,113 @@
+use std::path::Path;
+
+use nannou::image::{self, imageops, ImageResult, RgbaImage};
+use nannou::prelude::*;
+
+use super::chunks::TIDE_MULT;
+use super::map::Obstacle;
+
+/// pixels with less alpha than this are obstacles
+const OBSTACLE_ALPHA: u8 = 128;
+
+/// an environment painted as an image, resampled to whatever grid it's used on
+///
+/// red is how bright the sun is, green and blue are a current along x and y where
+/// half is still, and transparent pixels are obstacles.
+/// greyscale images only set the sun
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    image: RgbaImage,
+    has_current: bool,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> ImageResult<Scenario> {
+        let image = image::open(path)?;
+        Ok(Scenario {
+            has_current: image.color().has_color(),
+            // the image's first row is the top of the world, which has the highest y
+            image: imageops::flip_vertical(&image.to_rgba8()),
+        })
+    }
+    /// one pixel per cell, indexed by y * width + x
+    fn resample(&self, grid_size: (usize, usize)) -> Vec<image::Rgba<u8>> {
+        let resized = imageops::resize(
+            &self.image,
+            grid_size.0 as u32,
+            grid_size.1 as u32,
+            imageops::FilterType::Triangle,
+        );
+        resized.pixels().copied().collect()
+    }
+    pub fn sun(&self, grid_size: (usize, usize)) -> Vec<f32> {
+        self.resample(grid_size)
+            .iter()
+            .map(|pixel| pixel[0] as f32 / 255.)
+            .collect()
+    }
+    pub fn current(&self, grid_size: (usize, usize)) -> Vec<Vec2> {
+        self.resample(grid_size)
+            .iter()
+            .map(|pixel| {
+                if !self.has_current {
+                    return vec2(0., 0.);
+                }
+                let channel = |value: u8| value as f32 / 255. * 2. - 1.;
+                vec2(channel(pixel[1]), channel(pixel[2])) * TIDE_MULT
+            })
+            .collect()
+    }
+    /// a rectangle for each run of transparent cells in a row, where runs with the same ends in
+    /// neighbouring rows are merged so that they don't push nodes into each other
+    pub fn obstacles(&self, world_size: Vec2, cell_size: f32) -> Vec<Obstacle> {
+        let grid_size = (
+            (world_size.x / cell_size).ceil() as usize,
+            (world_size.y / cell_size).ceil() as usize,
+        );
+        let blocked: Vec<bool> = self
+            .resample(grid_size)
+            .iter()
+            .map(|pixel| pixel[3] < OBSTACLE_ALPHA)
+            .collect();
+        let rectangle = |(start, end, first_row): (usize, usize, usize), last_row: usize| {
+            let min = vec2(start as f32, first_row as f32) * cell_size;
+            let max = vec2(end as f32, last_row as f32 + 1.) * cell_size;
+            Obstacle::Polygon(vec![min, vec2(max.x, min.y), max, vec2(min.x, max.y)])
+        };
+        let mut obstacles = Vec::new();
+        // runs still growing upwards, as start, end and first row
+        let mut open: Vec<(usize, usize, usize)> = Vec::new();
+        for y in 0..grid_size.1 {
+            let mut runs = Vec::new();
+            let mut run_start = None;
+            for x in 0..=grid_size.0 {
+                let is_blocked = x < grid_size.0 && blocked[y * grid_size.0 + x];
+                match (run_start, is_blocked) {
+                    (None, true) => run_start = Some(x),
+                    (Some(start), false) => {
+                        runs.push((start, x));
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
+            let mut next_open = Vec::new();
+            for (start, end) in runs {
+                let first_row = match open.iter().position(|run| (run.0, run.1) == (start, end)) {
+                    Some(i) => open.swap_remove(i).2,
+                    None => y,
+                };
+                next_open.push((start, end, first_row));
+            }
+            // runs that didn't continue ended on the row below
+            for run in open {
+                obstacles.push(rectangle(run, y - 1));
+            }
+            open = next_open;
+        }
+        for run in open {
+            obstacles.push(rectangle(run, grid_size.1 - 1));
+        }
+        obstacles
+    }
+}
@@ -12,6 +12,11 @@ impl Tag {
         self.0[index] = rand::random();
     }
 
+    /// 1 for identical tags, 0 for the most different tags possible
+    pub fn similarity(&self, other: &Tag) -> f32 {
+        1. - self.distance(other) / (self.0.len() as f32).sqrt()
+    }
+
     pub fn distance(&self, other: &Tag) -> f32 {
         let mut distance = 0.;
         for i in 0..self.0.len() {